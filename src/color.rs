@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+
+/// Colors of capture groups 1, 2, 3, ... that `gN=` doesn't set, cycled through
+/// for patterns with more groups.
+const GROUP_PALETTE: [&str; 5] = ["01;32", "01;33", "01;34", "01;36", "01;35"];
+
+/// The SGR sequences used for each part of colored output.
+///
+/// The capabilities are those of GNU grep's `GREP_COLORS`: `ms` (or `mt`) for
+/// matched text, `sl` and `cx` for the rest of matching and context lines, `fn`
+/// for file names, `ln` for line numbers, `bn` for byte offsets and `se` for
+/// separators. On top of those, `gN` sets the color of capture group N inside a
+/// match; nested groups are drawn over the group around them.
+#[derive(Debug, Clone)]
+pub struct Colors {
+    pub selected_match: String,
+    pub selected_line: String,
+    pub context_line: String,
+    pub filename: String,
+    pub line_number: String,
+    pub byte_offset: String,
+    pub separator: String,
+    groups: BTreeMap<usize, String>,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            selected_match: "01;31".to_string(),
+            selected_line: String::new(),
+            context_line: String::new(),
+            filename: "35".to_string(),
+            line_number: "32".to_string(),
+            byte_offset: "32".to_string(),
+            separator: "36".to_string(),
+            groups: BTreeMap::new(),
+        }
+    }
+}
+
+impl Colors {
+    /// Applies a `GREP_COLORS`-style spec such as `ms=01;31:fn=35:g1=04`.
+    /// Capabilities GNU grep knows but rgrep has no use for are accepted and
+    /// ignored, so the same `GREP_COLORS` works for both.
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for entry in spec.split(':').filter(|e| !e.is_empty()) {
+            let (name, value) = entry.split_once('=').unwrap_or((entry, ""));
+            if !value.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                return Err(format!("invalid color '{}' for {}", value, name));
+            }
+            let value = value.to_string();
+            match name {
+                "mt" | "ms" => self.selected_match = value,
+                "sl" => self.selected_line = value,
+                "cx" => self.context_line = value,
+                "fn" => self.filename = value,
+                "ln" => self.line_number = value,
+                "bn" => self.byte_offset = value,
+                "se" => self.separator = value,
+                "mc" | "rv" | "ne" => {}
+                _ => match name.strip_prefix('g').and_then(|n| n.parse::<usize>().ok()) {
+                    Some(group) if group > 0 => {
+                        self.groups.insert(group, value);
+                    }
+                    _ => return Err(format!("unknown color capability '{}'", name)),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// The color of capture group `group` (1-based).
+    pub fn group(&self, group: usize) -> &str {
+        self.groups
+            .get(&group)
+            .map_or(GROUP_PALETTE[(group - 1) % GROUP_PALETTE.len()], |c| c)
+    }
+
+    /// Appends `text` wrapped in the SGR sequence `sgr`; an empty one leaves the
+    /// text uncolored.
+    pub fn paint(out: &mut Vec<u8>, sgr: &str, text: &[u8]) {
+        if sgr.is_empty() || text.is_empty() {
+            out.extend_from_slice(text);
+        } else {
+            out.extend_from_slice(format!("\x1b[{}m", sgr).as_bytes());
+            out.extend_from_slice(text);
+            out.extend_from_slice(b"\x1b[0m");
+        }
+    }
+}
@@ -1,48 +1,162 @@
-use std::{fs, path::Path};
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
 
-const RED: &str = "\x1b[1;31m";
-const RESET: &str = "\x1b[0m";
+use crate::color::Colors;
+use crate::encoding::{Decoding, Encoding};
+use crate::filetypes::TypeFilter;
+use crate::format::{Format, FormatMatch};
+use crate::glob::Glob;
+use crate::ignore::IgnoreStack;
+use crate::json;
+use crate::mmap;
+use crate::multiline;
+use crate::parser::input::DecodedLine;
+pub use crate::parser::regex::Captures;
+use crate::parser::regex::Regex;
+use crate::parser::types::Span;
+use crate::reader::{LineReader, LineSource, SliceLines};
+use crate::replace::Template;
 
-pub static mut ITERATION_COUNT: usize = 0;
+/// Set once any file or directory couldn't be read, for the exit status.
+static HAD_ERRORS: AtomicBool = AtomicBool::new(false);
+
+pub static ITERATION_COUNT: AtomicUsize = AtomicUsize::new(0);
 pub const MAX_ITERATIONS: usize = 2000;
 
 pub fn debug_log(config: Option<&Config>, message: &str) {
-    unsafe {
-        ITERATION_COUNT += 1;
-        if ITERATION_COUNT > MAX_ITERATIONS {
-            return;
-        }
+    if ITERATION_COUNT.fetch_add(1, Ordering::Relaxed) + 1 > MAX_ITERATIONS {
+        return;
     }
 
-    if let Some(c) = config {
-        if c.debug {
-            use std::io::Write;
-            let mut file = std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open("debug.log")
-                .unwrap();
-            writeln!(file, "{}", message).unwrap();
-        }
+    if let Some(c) = config
+        && c.debug
+    {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open("debug.log")
+            .unwrap();
+        writeln!(file, "{}", message).unwrap();
     }
 }
 
 fn reset_iteration_count() {
-    unsafe {
-        ITERATION_COUNT = 0;
-    }
+    ITERATION_COUNT.store(0, Ordering::Relaxed);
 }
 
 pub fn log_iteration_header(config: Option<&Config>, iteration: usize) {
-    if let Some(c) = config {
-        if c.debug {
-            use std::io::Write;
-            let mut file = std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open("debug.log")
-                .unwrap();
-            writeln!(file, "\n=== Iteration {} ===", iteration).unwrap();
+    if let Some(c) = config
+        && c.debug
+    {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open("debug.log")
+            .unwrap();
+        writeln!(file, "\n=== Iteration {} ===", iteration).unwrap();
+    }
+}
+
+/// Reports a file or directory that couldn't be searched as `rgrep: path: reason`
+/// (unless `-s`), and remembers it for the exit status. The search goes on.
+pub fn report_error(config: &Config, path: &str, error: &io::Error) {
+    HAD_ERRORS.store(true, Ordering::Relaxed);
+    if !config.no_messages {
+        let reason = error.to_string();
+        // "No such file or directory (os error 2)" reads better without the code
+        let reason = reason.split(" (os error").next().unwrap_or(&reason);
+        eprintln!("rgrep: {}: {}", path, reason);
+    }
+}
+
+/// Whether `report_error` was called during this run.
+pub fn had_errors() -> bool {
+    HAD_ERRORS.load(Ordering::Relaxed)
+}
+
+/// Why searching a file failed.
+#[derive(Debug)]
+pub enum SearchError {
+    /// The file couldn't be opened or read; only this file is affected
+    Input(io::Error),
+    /// Writing the output failed, which ends the whole search
+    Output(io::Error),
+}
+
+impl From<io::Error> for SearchError {
+    fn from(error: io::Error) -> Self {
+        SearchError::Output(error)
+    }
+}
+
+/// What gets printed for each searched file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Matching lines (the default)
+    Lines,
+    /// `-c`: number of matching lines (or matches with `--count-matches`)
+    Count,
+    /// `-l`: names of files with at least one match
+    FilesWithMatches,
+    /// `-L`: names of files without any match
+    FilesWithoutMatch,
+    /// `-q`: nothing at all, only the exit status
+    Quiet,
+}
+
+impl OutputMode {
+    /// Modes that only need to know whether a file matches at all.
+    pub fn stops_at_first_match(self) -> bool {
+        matches!(
+            self,
+            OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch | OutputMode::Quiet
+        )
+    }
+}
+
+/// `--binary-files`: what to do with files that look binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFiles {
+    /// Search them, but only say whether they match (the default)
+    Binary,
+    /// `-a`: search and print them like any other file
+    Text,
+    /// `-I`: treat them as not matching
+    WithoutMatch,
+}
+
+/// `--crlf`: whether lines end with `\r\n` rather than a bare `\n`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crlf {
+    /// A `\r` before the newline is part of the line (the default)
+    Never,
+    /// `--crlf`: a `\r` before the newline is part of the terminator, so `$`
+    /// matches before it
+    Always,
+    /// `--crlf=auto`: as `Always` in files whose first line ends with `\r\n`
+    Auto,
+}
+
+impl Crlf {
+    /// Whether the file starting with `block` has `\r\n` line endings.
+    pub fn applies(self, block: &[u8]) -> bool {
+        match self {
+            Crlf::Never => false,
+            Crlf::Always => true,
+            Crlf::Auto => block
+                .iter()
+                .position(|&b| b == b'\n')
+                .is_some_and(|end| end > 0 && block[end - 1] == b'\r'),
         }
     }
 }
@@ -50,290 +164,1009 @@ pub fn log_iteration_header(config: Option<&Config>, iteration: usize) {
 #[derive(Debug)]
 pub struct Config {
     pub pattern: String,
+    pub regex: Regex,
     pub filenames: Vec<String>,
-    pub color_mode: String,
+    /// `-r` without file operands searches the working directory, and its
+    /// paths are shown without a leading `./`
+    pub implicit_root: bool,
+    /// The color scheme, or `None` when output isn't colored
+    pub colors: Option<Colors>,
     pub recursive: bool,
     pub only_matching: bool,
-    pub multi_line: bool,
     pub debug: bool,
+    pub after_context: usize,
+    pub before_context: usize,
+    pub group_separator: Option<String>,
+    pub output_mode: OutputMode,
+    pub count_matches: bool,
+    pub max_count: Option<usize>,
+    pub max_total: Option<usize>,
+    /// `--mmap` / `--no-mmap`; `None` picks by file size
+    pub mmap: Option<bool>,
+    /// `-j`: number of walker and search threads
+    pub threads: usize,
+    /// `--sort path`: print files in path order instead of as they finish
+    pub sort_by_path: bool,
+    /// `--no-ignore`: don't read .gitignore, .ignore, .rgrepignore or the
+    /// global excludes file
+    pub no_ignore: bool,
+    /// `--hidden`: also search hidden files and directories
+    pub hidden: bool,
+    /// `--include` (`true`) and `--exclude` (`false`) globs, in command line order
+    pub file_globs: Vec<(Glob, bool)>,
+    /// `--exclude-dir` globs
+    pub exclude_dirs: Vec<Glob>,
+    /// `-t` / `-T` file types
+    pub file_types: TypeFilter,
+    pub binary_files: BinaryFiles,
+    /// `-s`: don't print messages about unreadable files
+    pub no_messages: bool,
+    /// `--follow` / `-R`: follow symlinks found while recursing
+    pub follow: bool,
+    /// `--max-depth`: how many levels below the command line paths to descend
+    pub max_depth: Option<usize>,
+    /// `--one-file-system`: don't descend into other file systems
+    pub one_file_system: bool,
+    /// `--json`: print JSON Lines events instead of text
+    pub json: bool,
+    /// `-U`: match against the whole input instead of line by line
+    pub multiline: bool,
+    /// `-z`: lines end with a NUL byte instead of a newline, in input and output
+    pub null_data: bool,
+    /// `-Z`: a NUL byte follows file names instead of `:` or a newline
+    pub null: bool,
+    pub crlf: Crlf,
+    /// `--encoding`: how to decode input; `None` goes by its byte order mark
+    pub encoding: Option<Encoding>,
+    /// `-b`: print the byte offset of each line, or of each match with `-o`
+    pub byte_offset: bool,
+    /// `--replace`: print lines with their matches replaced
+    pub replace: Option<Template>,
+    /// `--in-place`: write the replacements back to the files instead
+    pub in_place: bool,
+    /// `--dry-run`: show what `--in-place` would change as a unified diff
+    pub dry_run: bool,
+    /// `--backup=SUFFIX`: keep each rewritten file's original under this suffix
+    pub backup: Option<String>,
+    /// `--format`: print this template for each match instead of the line
+    pub format: Option<Format>,
+}
+
+impl Config {
+    /// The byte lines end with: NUL with `-z`, a newline otherwise.
+    pub fn line_terminator(&self) -> u8 {
+        if self.null_data { b'\0' } else { b'\n' }
+    }
+
+    /// Whether `-t`/`-T`, `--include`, `--exclude` or `--exclude-dir` leave `path`
+    /// out of a recursive search. As in GNU grep, the last matching `--include` or
+    /// `--exclude` decides for files; when none matches, a file is only left out
+    /// if the first of them is an `--include`.
+    /// How a path found by the walk is shown and searched: relative to the
+    /// working directory when that's the implicit root, as in GNU grep.
+    fn walked_path<'p>(&self, path: &'p Path) -> &'p Path {
+        match path.strip_prefix(".") {
+            Ok(relative) if self.implicit_root && !relative.as_os_str().is_empty() => relative,
+            _ => path,
+        }
+    }
+
+    fn filtered_out(&self, path: &Path, is_dir: bool) -> bool {
+        if is_dir {
+            return self.exclude_dirs.iter().any(|glob| glob.matches_path(path));
+        }
+        if !self.file_types.allows(path) {
+            return true;
+        }
+        match self
+            .file_globs
+            .iter()
+            .rev()
+            .find(|(glob, _)| glob.matches_path(path))
+        {
+            Some(&(_, include)) => !include,
+            None => self.file_globs.first().is_some_and(|&(_, include)| include),
+        }
+    }
+}
+
+/// A directory for the walk to read, with the ignore rules that apply to its
+/// entries' parents.
+pub struct WalkDir {
+    pub path: PathBuf,
+    pub ignore: Arc<IgnoreStack>,
+    /// 0 for a directory given on the command line, for `--max-depth`
+    depth: usize,
+    /// Device of the command line directory, for `--one-file-system`
+    device: Option<u64>,
+    /// Device and inode of this directory and those above it, for spotting
+    /// symlink loops with `--follow`
+    ancestors: Vec<(u64, u64)>,
+}
+
+/// Device and inode of a file, where the platform has them.
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+impl WalkDir {
+    fn root(path: &Path, metadata: &fs::Metadata, config: &Config) -> WalkDir {
+        let id = file_id(metadata);
+        WalkDir {
+            path: path.to_path_buf(),
+            ignore: IgnoreStack::root(path, config.no_ignore),
+            depth: 0,
+            device: id.map(|(device, _)| device),
+            ancestors: id.into_iter().collect(),
+        }
+    }
+
+    /// The walk state for `path`, an entry of this directory, or `None` when it
+    /// must not be entered: with `--one-file-system` because it is on another
+    /// file system, or with `--follow` because it is one of its own ancestors.
+    fn subdir(&self, path: PathBuf, ignore: Arc<IgnoreStack>, config: &Config) -> Option<WalkDir> {
+        let mut ancestors = Vec::new();
+        if config.follow || config.one_file_system {
+            let id = match fs::metadata(&path) {
+                Ok(metadata) => file_id(&metadata),
+                Err(e) => {
+                    report_error(config, &config.walked_path(&path).to_string_lossy(), &e);
+                    return None;
+                }
+            };
+            if config.one_file_system && id.is_some_and(|(device, _)| Some(device) != self.device) {
+                return None;
+            }
+            if config.follow {
+                if id.is_some_and(|id| self.ancestors.contains(&id)) {
+                    if !config.no_messages {
+                        eprintln!(
+                            "rgrep: warning: {}: recursive directory loop",
+                            path.display()
+                        );
+                    }
+                    return None;
+                }
+                ancestors = self.ancestors.clone();
+                ancestors.extend(id);
+            }
+        }
+
+        Some(WalkDir {
+            path,
+            ignore,
+            depth: self.depth + 1,
+            device: self.device,
+            ancestors,
+        })
+    }
+}
+
+/// Receives what `walk_roots` and `collect_from_dir` find while walking.
+pub trait WalkVisitor {
+    /// A file to search; returning `false` stops the walk.
+    fn file(&mut self, path: &Path) -> bool;
+    /// A directory to descend into; returning `false` stops the walk.
+    fn dir(&mut self, dir: WalkDir) -> bool;
+}
+
+/// Visitor for the serial walk: files go to a closure and directories are
+/// descended into right away, depth first.
+struct SerialWalk<'a> {
+    config: &'a Config,
+    visit: &'a mut dyn FnMut(&Path) -> bool,
+}
+
+impl WalkVisitor for SerialWalk<'_> {
+    fn file(&mut self, path: &Path) -> bool {
+        (self.visit)(path)
+    }
+
+    fn dir(&mut self, dir: WalkDir) -> bool {
+        let config = self.config;
+        collect_from_dir(&dir, config, self)
+    }
 }
 
-pub fn collect_files_recursive(paths: &[String], recursive: bool) -> Vec<String> {
-    let mut files = Vec::new();
+/// Walks `config.filenames`, handing every file found to `visit`. Walking stops
+/// as soon as `visit` returns `false`; the return value tells whether the walk
+/// completed.
+pub fn collect_files_recursive(config: &Config, visit: &mut dyn FnMut(&Path) -> bool) -> bool {
+    walk_roots(config, &mut SerialWalk { config, visit })
+}
 
-    for path_str in paths {
+/// Hands the paths given on the command line to `visitor`: files as they are and
+/// directories, with `-r`, as the first ones to descend into. Missing paths and
+/// directories without `-r` are reported and skipped.
+pub fn walk_roots(config: &Config, visitor: &mut dyn WalkVisitor) -> bool {
+    for path_str in &config.filenames {
         let path = Path::new(path_str);
-        if path.is_file() {
-            files.push(path_str.clone());
-        } else if path.is_dir() {
-            if recursive {
-                collect_from_dir(path, &mut files);
-            } else {
-                eprintln!("{}: Is a directory", path_str);
-                std::process::exit(1);
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                report_error(config, path_str, &e);
+                continue;
             }
+        };
+
+        let keep_going = if metadata.is_dir() {
+            if !config.recursive {
+                report_error(config, path_str, &io::Error::other("Is a directory"));
+                continue;
+            }
+            visitor.dir(WalkDir::root(path, &metadata, config))
         } else {
-            eprintln!("{}: No such file or directory", path_str);
-            std::process::exit(1);
+            visitor.file(path)
+        };
+        if !keep_going {
+            return false;
         }
     }
 
-    files
-}
-
-fn collect_from_dir(dir: &Path, files: &mut Vec<String>) {
-    if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_file() {
-                files.push(path.to_string_lossy().to_string());
-            } else if path.is_dir() {
-                // Skip hidden directories like .git
-                if !path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .map(|s| s.starts_with('.'))
-                    .unwrap_or(false)
-                {
-                    collect_from_dir(&path, files);
+    true
+}
+
+/// Reads one directory and hands its files and subdirectories to `visitor`,
+/// leaving out hidden entries (unless `--hidden`), those filtered out by file
+/// type or `--include`/`--exclude`/`--exclude-dir` and those matched by ignore files
+/// (unless `--no-ignore`). Left-out directories are never read, and neither are
+/// `.git` directories, whatever the options.
+///
+/// Symlinks are skipped unless `--follow` (or `-R`) is given; as in GNU grep,
+/// only those on the command line are followed by plain `-r`.
+pub fn collect_from_dir(dir: &WalkDir, config: &Config, visitor: &mut dyn WalkVisitor) -> bool {
+    if config.max_depth.is_some_and(|max| dir.depth >= max) {
+        return true;
+    }
+    let ignore = if config.no_ignore {
+        Arc::clone(&dir.ignore)
+    } else {
+        dir.ignore.child(&dir.path)
+    };
+
+    let entries = match fs::read_dir(&dir.path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            report_error(config, &config.walked_path(&dir.path).to_string_lossy(), &e);
+            return true;
+        }
+    };
+
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                report_error(config, &config.walked_path(&dir.path).to_string_lossy(), &e);
+                continue;
+            }
+        };
+        if !config.hidden && entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(e) => {
+                report_error(config, &config.walked_path(&path).to_string_lossy(), &e);
+                continue;
+            }
+        };
+        let file_type = if !file_type.is_symlink() {
+            file_type
+        } else if !config.follow {
+            continue;
+        } else {
+            match fs::metadata(&path) {
+                Ok(metadata) => metadata.file_type(),
+                Err(e) => {
+                    // A broken link
+                    report_error(config, &config.walked_path(&path).to_string_lossy(), &e);
+                    continue;
                 }
             }
+        };
+        // Sockets, FIFOs and devices are skipped
+        let is_dir = file_type.is_dir();
+        if !is_dir && !file_type.is_file() || is_dir && entry.file_name() == ".git" {
+            continue;
+        }
+        if config.filtered_out(&path, is_dir) || ignore.is_ignored(&path, is_dir) {
+            continue;
+        }
+
+        let keep_going = if is_dir {
+            match dir.subdir(path, Arc::clone(&ignore), config) {
+                Some(subdir) => visitor.dir(subdir),
+                None => true,
+            }
+        } else {
+            visitor.file(config.walked_path(&path))
+        };
+        if !keep_going {
+            return false;
         }
     }
+    true
 }
 
-pub fn search_file(content: &str, config: &Config, filename: Option<&str>) -> bool {
-    let mut matched_any = false;
-    let is_colored = match config.color_mode.as_str() {
-        "always" => true,
-        "auto" => atty::is(atty::Stream::Stdout),
-        _ => false,
-    };
+/// Searches one file, through a memory map when `mmap::should_mmap` says so and
+/// with buffered reads otherwise (or if mapping fails). Files that need
+/// transcoding are always read, through a `Decoder`.
+pub fn search_path(
+    path: &Path,
+    config: &Config,
+    filename: Option<&Path>,
+    limit: Option<usize>,
+    grouped: &mut bool,
+    out: &mut dyn Write,
+) -> Result<FileMatches, SearchError> {
+    let file = File::open(path).map_err(SearchError::Input)?;
+    if mmap::should_mmap(&file, config.mmap)
+        && let Ok(map) = mmap::Mmap::map(&file)
+    {
+        let decoding = Decoding::detect(map.as_bytes(), config.encoding);
+        if decoding.encoding == Encoding::Utf8 {
+            return search_file(
+                SliceLines::new(map.as_bytes(), config.line_terminator(), decoding),
+                config,
+                filename,
+                limit,
+                grouped,
+                out,
+            );
+        }
+    }
+    let reader = LineReader::decoded(file, config.line_terminator(), config.encoding)
+        .map_err(SearchError::Input)?;
+    search_file(reader, config, filename, limit, grouped, out)
+}
+
+/// Where a line starts in the original input, to give `-b` and `{offset}` the
+/// original byte offset of a match in it.
+#[derive(Debug, Clone, Copy)]
+pub struct LineOffset {
+    pub start: usize,
+    pub decoding: Decoding,
+}
+
+impl LineOffset {
+    /// Byte offset in the original input of char `index` of `line`.
+    pub fn of(&self, line: &DecodedLine, index: usize) -> usize {
+        self.start + self.decoding.original_len(line.slice(0, index))
+    }
+}
+
+/// What searching one file found.
+pub struct FileMatches {
+    /// Matching lines, or individual matches with `--count-matches`
+    pub count: usize,
+    /// The file looked binary, so its lines weren't printed
+    pub binary: bool,
+}
+
+/// Whether the start of a file looks binary: GNU grep's NUL byte heuristic, which
+/// `-z` turns off since NUL bytes end its lines.
+pub fn looks_binary(block: &[u8], config: &Config) -> bool {
+    config.binary_files != BinaryFiles::Text && !config.null_data && block.contains(&0)
+}
+
+/// Searches `reader` line by line and returns the number of matching lines, or of
+/// individual matches with `--count-matches`. Lines are only printed in
+/// `OutputMode::Lines`, and not for binary files unless `-a` is given.
+///
+/// Each line's raw bytes are checked with `Regex::may_match` first, and only
+/// decoded for the matcher if that leaves a chance of a match; context lines
+/// are printed from the raw bytes.
+///
+/// Reading stops after `limit` matching lines (once any trailing context has been
+/// printed), or after the first one in modes that only need to know whether the
+/// file matches, so the rest of the input is never read.
+///
+/// `grouped` says whether an earlier input already printed a context group, so
+/// this one's first group is separated from it; it's set once one is printed.
+pub fn search_file<S: LineSource>(
+    mut reader: S,
+    config: &Config,
+    filename: Option<&Path>,
+    limit: Option<usize>,
+    grouped: &mut bool,
+    out: &mut dyn Write,
+) -> Result<FileMatches, SearchError> {
+    let first_block = reader.first_block().map_err(SearchError::Input)?;
+    let binary = looks_binary(first_block, config);
+    let crlf = config.crlf.applies(first_block);
+    if binary && config.binary_files == BinaryFiles::WithoutMatch {
+        return Ok(FileMatches { count: 0, binary });
+    }
+    if config.json && config.output_mode == OutputMode::Lines {
+        return json::search_file(reader, config, filename, limit, binary, crlf, out);
+    }
+    if config.multiline {
+        return multiline::search_file(reader, config, filename, limit, binary, grouped, out);
+    }
+    // A binary file's lines aren't printed, so its first match is all we need
+    let only_first_match = config.output_mode.stops_at_first_match()
+        || binary && config.output_mode == OutputMode::Lines;
+
+    let mut count = 0;
+    let mut matched_lines = 0;
+    let mut window = ContextWindow::new(config, *grouped);
+
+    if limit == Some(0) {
+        return Ok(FileMatches { count: 0, binary });
+    }
 
-    for line in content.lines() {
-        let matched = if config.multi_line {
-            check_multiples_matching_patterns(line, &config.pattern, is_colored, filename)
+    let decoding = reader.decoding();
+    let terminator_len = decoding.original_len(&[config.line_terminator()]);
+    let mut next_index = 0;
+    let mut offset = decoding.bom_len;
+    while let Some(bytes) = reader.next_line().map_err(SearchError::Input)? {
+        let (line_index, line_offset) = (next_index, offset);
+        next_index += 1;
+        offset += decoding.original_len(bytes) + terminator_len;
+        let position = LineOffset {
+            start: line_offset,
+            decoding,
+        };
+
+        if limit.is_some_and(|limit| matched_lines >= limit) {
+            // Past the last allowed match, only trailing context is left to print
+            if !window.in_after_context() {
+                break;
+            }
+            window.non_match(out, line_index, line_offset, bytes, filename)?;
+            continue;
+        }
+
+        // A line without the literal every match needs isn't even decoded
+        let line = config
+            .regex
+            .may_match(bytes)
+            .then(|| DecodedLine::new(bytes).with_crlf(crlf));
+        let matching = || line.as_ref().filter(|line| match_pattern(line, config));
+
+        if config.output_mode != OutputMode::Lines || binary {
+            if let Some(line) = matching() {
+                matched_lines += 1;
+                count += if config.count_matches {
+                    match_captures(line.text(), &config.regex).len()
+                } else {
+                    1
+                };
+                if only_first_match {
+                    break;
+                }
+            }
+            continue;
+        }
+
+        let matched = if let Some(template) = &config.replace {
+            // No context around the bare replacements of -o, as with plain -o
+            let with_context = !config.only_matching;
+            if let Some(line) = matching() {
+                if with_context {
+                    window.before_match(out, line_index, filename)?;
+                }
+                print_replaced_line(out, line, config, template, filename, position)?;
+                if with_context {
+                    window.after_match(line_index);
+                }
+                true
+            } else {
+                if with_context {
+                    window.non_match(out, line_index, line_offset, bytes, filename)?;
+                }
+                false
+            }
+        } else if let Some(format) = &config.format {
+            match &line {
+                Some(line) => print_formatted_matches(
+                    out, line, config, format, line_index, position, filename,
+                )?,
+                None => false,
+            }
         } else if config.only_matching {
-            check_only_matching_patterns(line, &config.pattern, is_colored, filename)
+            match &line {
+                Some(line) => print_only_matching(out, line, config, filename, position)?,
+                None => false,
+            }
+        } else if let Some(line) = matching() {
+            window.before_match(out, line_index, filename)?;
+            print_matching_line(out, line, config, filename, line_offset)?;
+            window.after_match(line_index);
+            true
         } else {
-            check_pattern(line, &config.pattern, is_colored, filename, config)
+            window.non_match(out, line_index, line_offset, bytes, filename)?;
+            false
         };
 
         if matched {
-            matched_any = true;
+            matched_lines += 1;
+            count += 1;
+        }
+    }
+
+    *grouped = window.grouped();
+    Ok(FileMatches { count, binary })
+}
+
+/// Prints the per-file summary for `-c`, `-l` and `-L`, or the notice that
+/// stands in for the lines of a matching binary file, and returns whether the
+/// file counts towards a successful exit status.
+pub fn report_file(
+    out: &mut dyn Write,
+    config: &Config,
+    label: &Path,
+    prefix: bool,
+    matches: &FileMatches,
+) -> io::Result<bool> {
+    let count = matches.count;
+    let colors = config.colors.as_ref();
+    let mut name = Vec::new();
+    paint(&mut name, colors, |c| &c.filename, path_bytes(label));
+    Ok(match config.output_mode {
+        OutputMode::Count => {
+            if prefix {
+                out.write_all(&name)?;
+                let mut separator = Vec::new();
+                if config.null {
+                    separator.push(b'\0');
+                } else {
+                    paint(&mut separator, colors, |c| &c.separator, b":");
+                }
+                out.write_all(&separator)?;
+            }
+            writeln!(out, "{}", count)?;
+            count > 0
+        }
+        OutputMode::FilesWithMatches => {
+            if count > 0 {
+                out.write_all(&name)?;
+                out.write_all(if config.null { b"\0" } else { b"\n" })?;
+            }
+            count > 0
+        }
+        OutputMode::FilesWithoutMatch => {
+            if count == 0 {
+                out.write_all(&name)?;
+                out.write_all(if config.null { b"\0" } else { b"\n" })?;
+            }
+            count == 0
+        }
+        OutputMode::Lines => {
+            // JSON has the `binary` flag of the `end` event instead
+            if matches.binary && count > 0 && !config.json {
+                out.write_all(b"Binary file ")?;
+                out.write_all(path_bytes(label))?;
+                writeln!(out, " matches")?;
+            }
+            count > 0
+        }
+        OutputMode::Quiet => count > 0,
+    })
+}
+
+/// Before/after context bookkeeping for `-A`, `-B` and `-C`.
+///
+/// Preceding lines are kept in a ring buffer of `before_context` entries and only
+/// printed once a match shows up; windows that touch or overlap are merged, and a
+/// group separator is printed between windows that don't.
+pub struct ContextWindow<'a> {
+    /// Index, byte offset and text of each line
+    before: VecDeque<(usize, usize, Vec<u8>)>,
+    before_len: usize,
+    after_len: usize,
+    after_left: usize,
+    last_printed: Option<usize>,
+    /// An earlier input printed a group, which the first one here follows
+    grouped: bool,
+    config: &'a Config,
+}
+
+impl<'a> ContextWindow<'a> {
+    pub fn new(config: &'a Config, grouped: bool) -> Self {
+        ContextWindow {
+            before: VecDeque::with_capacity(config.before_context),
+            before_len: config.before_context,
+            after_len: config.after_context,
+            after_left: 0,
+            last_printed: None,
+            grouped,
+            config,
+        }
+    }
+
+    /// Whether a context group has been printed, here or in an earlier input.
+    pub fn grouped(&self) -> bool {
+        self.enabled() && (self.grouped || self.last_printed.is_some())
+    }
+
+    fn enabled(&self) -> bool {
+        self.before_len > 0 || self.after_len > 0
+    }
+
+    pub fn in_after_context(&self) -> bool {
+        self.after_left > 0
+    }
+
+    /// Prints the group separator if needed, then the buffered before-context.
+    pub fn before_match(
+        &mut self,
+        out: &mut dyn Write,
+        line_index: usize,
+        filename: Option<&Path>,
+    ) -> io::Result<()> {
+        let first = self
+            .before
+            .front()
+            .map_or(line_index, |(index, _, _)| *index);
+        let separated = match self.last_printed {
+            Some(last) => first > last + 1,
+            None => self.grouped,
+        };
+        if self.enabled() && separated {
+            print_group_separator(out, self.config)?;
+        }
+        for (_, offset, line) in self.before.drain(..) {
+            print_context_line(out, self.config, filename, offset, &line)?;
         }
+        Ok(())
+    }
+
+    pub fn after_match(&mut self, line_index: usize) {
+        self.last_printed = Some(line_index);
+        self.after_left = self.after_len;
     }
 
-    matched_any
+    /// Prints `line` as after-context, or keeps a copy of it in case it turns
+    /// out to be before-context; without either, it's dropped uncopied.
+    pub fn non_match(
+        &mut self,
+        out: &mut dyn Write,
+        line_index: usize,
+        offset: usize,
+        line: &[u8],
+        filename: Option<&Path>,
+    ) -> io::Result<()> {
+        if self.after_left > 0 {
+            self.after_left -= 1;
+            self.last_printed = Some(line_index);
+            print_context_line(out, self.config, filename, offset, line)?;
+        } else if self.before_len > 0 {
+            if self.before.len() == self.before_len {
+                self.before.pop_front();
+            }
+            self.before.push_back((line_index, offset, line.to_vec()));
+        }
+        Ok(())
+    }
 }
 
-fn match_pattern(input: &str, pattern: &str, config: &Config) -> bool {
+/// Prints the line between two context groups, unless `--no-group-separator`
+/// turned it off.
+pub fn print_group_separator(out: &mut dyn Write, config: &Config) -> io::Result<()> {
+    let Some(separator) = &config.group_separator else {
+        return Ok(());
+    };
+    let mut output = Vec::new();
+    paint(
+        &mut output,
+        config.colors.as_ref(),
+        |c| &c.separator,
+        separator.as_bytes(),
+    );
+    output.push(b'\n');
+    out.write_all(&output)
+}
+
+/// A path's raw bytes, for printing file names exactly as they are on disk.
+pub fn path_bytes(path: &Path) -> &[u8] {
+    path.as_os_str().as_encoded_bytes()
+}
+
+/// Appends `text` to `out` in the color `pick` chooses from the scheme, or
+/// as is when output isn't colored.
+fn paint(out: &mut Vec<u8>, colors: Option<&Colors>, pick: fn(&Colors) -> &str, text: &[u8]) {
+    match colors {
+        Some(colors) => Colors::paint(out, pick(colors), text),
+        None => out.extend_from_slice(text),
+    }
+}
+
+/// Writes a line as raw bytes with the optional filename prefix and, with `-b`,
+/// its byte `offset`; `separator` is `:` for matching lines and `-` for context
+/// lines, as in GNU grep, and a NUL byte after the filename with `-Z`.
+pub fn print_line(
+    out: &mut dyn Write,
+    config: &Config,
+    filename: Option<&Path>,
+    separator: char,
+    offset: usize,
+    output: &[u8],
+) -> io::Result<()> {
+    let colors = config.colors.as_ref();
+    let mut line = Vec::with_capacity(output.len() + 1);
+    if let Some(fname) = filename {
+        paint(&mut line, colors, |c| &c.filename, path_bytes(fname));
+        if config.null {
+            line.push(b'\0');
+        } else {
+            paint(&mut line, colors, |c| &c.separator, &[separator as u8]);
+        }
+    }
+    if config.byte_offset {
+        paint(
+            &mut line,
+            colors,
+            |c| &c.byte_offset,
+            offset.to_string().as_bytes(),
+        );
+        paint(&mut line, colors, |c| &c.separator, &[separator as u8]);
+    }
+    line.extend_from_slice(output);
+    line.push(config.line_terminator());
+    out.write_all(&line)
+}
+
+fn print_context_line(
+    out: &mut dyn Write,
+    config: &Config,
+    filename: Option<&Path>,
+    offset: usize,
+    line: &[u8],
+) -> io::Result<()> {
+    let mut output = Vec::with_capacity(line.len());
+    paint(
+        &mut output,
+        config.colors.as_ref(),
+        |c| &c.context_line,
+        line,
+    );
+    print_line(out, config, filename, '-', offset, &output)
+}
+
+/// Appends the match at `span` of `line` in the match color, with each capture
+/// group in `groups` drawn in its own color; a group nested in another is drawn
+/// over it.
+pub fn push_match(
+    out: &mut Vec<u8>,
+    line: &DecodedLine,
+    (start, end): (usize, usize),
+    groups: &[Option<Span>],
+    colors: Option<&Colors>,
+) {
+    let Some(colors) = colors else {
+        out.extend_from_slice(line.slice(start, end));
+        return;
+    };
+    let groups: Vec<(usize, Span)> = groups
+        .iter()
+        .enumerate()
+        .filter_map(|(i, group)| group.map(|span| (i + 1, span)))
+        .collect();
+
+    let mut pos = start;
+    while pos < end {
+        // The innermost group here is the one that starts last, or ends first
+        // among those starting at the same place
+        let innermost = groups
+            .iter()
+            .filter(|(_, (s, e))| *s <= pos && pos < *e)
+            .max_by_key(|(_, (s, e))| (*s, std::cmp::Reverse(*e)));
+        let next = groups
+            .iter()
+            .flat_map(|(_, (s, e))| [*s, *e])
+            .filter(|&boundary| boundary > pos)
+            .fold(end, usize::min);
+        let sgr = innermost.map_or(colors.selected_match.as_str(), |(n, _)| colors.group(*n));
+        Colors::paint(out, sgr, line.slice(pos, next));
+        pos = next;
+    }
+}
+
+pub fn match_pattern(line: &DecodedLine, config: &Config) -> bool {
     reset_iteration_count();
     debug_log(
         Some(config),
-        &format!("Matching pattern '{}' against input '{}'", pattern, input),
+        &format!(
+            "Matching pattern '{}' against input '{}'",
+            config.pattern,
+            String::from_utf8_lossy(line.bytes())
+        ),
     );
-    let mut tokens = crate::parser::token::tokenize(pattern);
-    let mut group_counter = 1;
-    crate::parser::token::assign_group_numbers(&mut tokens, &mut group_counter);
     debug_log(
         Some(config),
-        &format!("Tokens after group assignment: {:?}", tokens),
+        &format!("Tokens after group assignment: {:?}", config.regex.tokens()),
     );
-    let result = crate::parser::token::match_tokens(input, &tokens, config);
+    let result = config.regex.is_match(line.text(), Some(config));
     debug_log(Some(config), &format!("Match result: {}", result));
     result
 }
 
-fn highlight_matches_in_line(input_line: &str, pattern: &str) -> String {
-    let mut tokens = crate::parser::token::tokenize(pattern);
-    let mut group_counter = 1;
-    crate::parser::token::assign_group_numbers(&mut tokens, &mut group_counter);
-    let input_chars: Vec<char> = input_line.chars().collect();
-    let has_start = matches!(
-        tokens.first(),
-        Some(crate::parser::token::Token::StartAnchor)
-    );
-    let has_end = matches!(tokens.last(), Some(crate::parser::token::Token::EndAnchor));
-    let mut pos = 0;
-    let mut result = String::new();
-    while pos < input_chars.len() {
-        if has_start && pos != 0 {
-            break;
-        }
-        let mut dummy_captures = Vec::new();
-        if let Some(match_len) = crate::parser::token::matches_from_range(
-            &input_chars,
-            &tokens,
-            pos,
-            None,
-            &mut dummy_captures,
-        ) {
-            let match_end = pos + match_len;
-            if has_end && match_end != input_chars.len() {
-                // No match, add the current char and continue
-                result.push(input_chars[pos]);
-                pos += 1;
-                continue;
-            }
-            // Add the matched part with color
-            let start_byte = char_to_byte(input_line, pos);
-            let end_byte = char_to_byte(input_line, match_end);
-            result.push_str(&format!(
-                "{}{}{}",
-                RED,
-                &input_line[start_byte..end_byte],
-                RESET
-            ));
-            pos = match_end;
-        } else {
-            // No match, add the current char
-            result.push(input_chars[pos]);
-            pos += 1;
-        }
-    }
-    // Add remaining characters if any
-    if pos < input_chars.len() {
-        let start_byte = char_to_byte(input_line, pos);
-        result.push_str(&input_line[start_byte..]);
+/// A matching line with its matches colored, and the text between them in the
+/// selected line color.
+fn highlight_matches_in_line(line: &DecodedLine, regex: &Regex, colors: &Colors) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut last_end = 0;
+    for ((start, end), groups) in match_captures(line.text(), regex) {
+        Colors::paint(
+            &mut result,
+            &colors.selected_line,
+            line.slice(last_end, start),
+        );
+        push_match(&mut result, line, (start, end), &groups, Some(colors));
+        last_end = end;
     }
+    Colors::paint(
+        &mut result,
+        &colors.selected_line,
+        line.slice(last_end, line.chars.len()),
+    );
     result
 }
 
-pub fn check_pattern(
-    input_line: &str,
-    pattern: &str,
-    is_colored: bool,
-    filename: Option<&str>,
+/// Prints a matching line, which starts at byte `offset` of the input.
+fn print_matching_line(
+    out: &mut dyn Write,
+    line: &DecodedLine,
     config: &Config,
-) -> bool {
-    if match_pattern(&input_line, &pattern, config) {
-        let output = if is_colored {
-            highlight_matches_in_line(input_line, pattern)
-        } else {
-            input_line.to_string()
-        };
-
-        match filename {
-            Some(fname) => println!("{}:{}", fname, output),
-            None => println!("{}", output),
-        }
-        true
-    } else {
-        false
+    filename: Option<&Path>,
+    offset: usize,
+) -> io::Result<()> {
+    match &config.colors {
+        Some(scheme) => print_line(
+            out,
+            config,
+            filename,
+            ':',
+            offset,
+            &highlight_matches_in_line(line, &config.regex, scheme),
+        ),
+        None => print_line(out, config, filename, ':', offset, line.bytes()),
     }
 }
 
-fn char_to_byte(s: &str, char_index: usize) -> usize {
-    s.chars().take(char_index).map(|c| c.len_utf8()).sum()
+/// `--replace`: prints `line` with every match replaced by the expanded
+/// `template`, or with `-o` each replacement on its own line.
+fn print_replaced_line(
+    out: &mut dyn Write,
+    line: &DecodedLine,
+    config: &Config,
+    template: &Template,
+    filename: Option<&Path>,
+    position: LineOffset,
+) -> io::Result<()> {
+    let colors = config.colors.as_ref();
+    if !config.only_matching {
+        let output = replace_matches(line, &config.regex, template, colors);
+        return print_line(out, config, filename, ':', position.start, &output);
+    }
+    for ((start, end), captures) in match_captures(line.text(), &config.regex) {
+        let mut replaced = Vec::new();
+        paint(
+            &mut replaced,
+            colors,
+            |c| &c.selected_match,
+            &template.expand(line.slice(start, end), &group_texts(line, &captures)),
+        );
+        print_line(
+            out,
+            config,
+            filename,
+            ':',
+            position.of(line, start),
+            &replaced,
+        )?;
+    }
+    Ok(())
 }
 
-pub fn check_only_matching_patterns(
-    input_line: &str,
-    pattern: &str,
-    is_colored: bool,
-    filename: Option<&str>,
-) -> bool {
-    let mut tokens = crate::parser::token::tokenize(pattern);
-    let mut group_counter = 1;
-    crate::parser::token::assign_group_numbers(&mut tokens, &mut group_counter);
-    let input_chars: Vec<char> = input_line.chars().collect();
-    let has_start = matches!(
-        tokens.first(),
-        Some(crate::parser::token::Token::StartAnchor)
-    );
-    let has_end = matches!(tokens.last(), Some(crate::parser::token::Token::EndAnchor));
-    let mut pos = 0;
-    let mut found = false;
-    while pos < input_chars.len() {
-        if has_start && pos != 0 {
-            break;
-        }
-        let mut dummy_captures = Vec::new();
-        if let Some(match_len) = crate::parser::token::matches_from_range(
-            &input_chars,
-            &tokens,
-            pos,
-            None,
-            &mut dummy_captures,
-        ) {
-            let match_end = pos + match_len;
-            if has_end && match_end != input_chars.len() {
-                pos += 1;
-                continue;
-            }
-            let start_byte = char_to_byte(input_line, pos);
-            let end_byte = char_to_byte(input_line, match_end);
-            let output = if is_colored {
-                format!("{}{}{}", RED, &input_line[start_byte..end_byte], RESET)
-            } else {
-                input_line[start_byte..end_byte].to_string()
-            };
+/// `line` with every match of `pattern` replaced by the expanded `template`.
+pub fn replace_matches(
+    line: &DecodedLine,
+    regex: &Regex,
+    template: &Template,
+    colors: Option<&Colors>,
+) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut last_end = 0;
+    // Empty matches get a replacement too, as in sed
+    for ((start, end), captures) in regex.find_iter(line.text()) {
+        output.extend_from_slice(line.slice(last_end, start));
+        paint(
+            &mut output,
+            colors,
+            |c| &c.selected_match,
+            &template.expand(line.slice(start, end), &group_texts(line, &captures)),
+        );
+        last_end = end;
+    }
+    output.extend_from_slice(line.slice(last_end, line.chars.len()));
+    output
+}
 
-            match filename {
-                Some(fname) => println!("{}:{}", fname, output),
-                None => println!("{}", output),
-            }
-            found = true;
-            pos = match_end;
-        } else {
-            pos += 1;
+/// `--format`: prints the template once for every non-empty match in `line`,
+/// which starts at `position` in the input, and returns whether the line matched
+/// at all.
+fn print_formatted_matches(
+    out: &mut dyn Write,
+    line: &DecodedLine,
+    config: &Config,
+    format: &Format,
+    line_index: usize,
+    position: LineOffset,
+    filename: Option<&Path>,
+) -> io::Result<bool> {
+    let path = filename.map_or(b"(standard input)".as_slice(), path_bytes);
+    let mut matched = false;
+    for ((start, end), groups) in config.regex.find_iter(line.text()) {
+        matched = true;
+        if start == end {
+            continue;
         }
+        let offset = position.of(line, start);
+        let captures = group_texts(line, &groups);
+        let found = FormatMatch {
+            path,
+            line_number: line_index + 1,
+            column: offset - position.start + 1,
+            offset,
+            text: line.slice(start, end),
+            captures: &captures,
+        };
+        format.write(out, &found, config.colors.as_ref())?;
     }
-    found
-}
-
-pub fn check_multiples_matching_patterns(
-    line: &str,
-    pattern: &str,
-    is_colored: bool,
-    filename: Option<&str>,
-) -> bool {
-    let mut tokens = crate::parser::token::tokenize(pattern);
-    let mut group_counter = 1;
-    crate::parser::token::assign_group_numbers(&mut tokens, &mut group_counter);
-    let input_chars: Vec<char> = line.chars().collect();
-    let has_start = matches!(
-        tokens.first(),
-        Some(crate::parser::token::Token::StartAnchor)
-    );
-    let has_end = matches!(tokens.last(), Some(crate::parser::token::Token::EndAnchor));
-    let mut pos = 0;
-    let mut found = false;
-    while pos < input_chars.len() {
-        if has_start && pos != 0 {
-            break;
-        }
-        let mut dummy_captures = Vec::new();
-        if let Some(match_len) = crate::parser::token::matches_from_range(
-            &input_chars,
-            &tokens,
-            pos,
-            None,
-            &mut dummy_captures,
-        ) {
-            let match_end = pos + match_len;
-            if has_end && match_end != input_chars.len() {
-                pos += 1;
-                continue;
-            }
-            let start_byte = char_to_byte(line, pos);
-            let end_byte = char_to_byte(line, match_end);
-            let output = if is_colored {
-                format!("{}{}{}", RED, &line[start_byte..end_byte], RESET)
-            } else {
-                line[start_byte..end_byte].to_string()
-            };
+    Ok(matched)
+}
 
-            match filename {
-                Some(fname) => println!("{}:{}", fname, output),
-                None => println!("{}", output),
-            }
-            found = true;
-            pos = match_end;
-        } else {
-            pos += 1;
+/// The bytes of each capture group of a match in `line`.
+pub fn group_texts<'a>(line: &DecodedLine<'a>, groups: &[Option<Span>]) -> Vec<Option<&'a [u8]>> {
+    groups
+        .iter()
+        .map(|group| group.map(|(start, end)| line.slice(start, end)))
+        .collect()
+}
+
+/// The non-empty matches in a line with their capture groups: the ones output
+/// shows, see `parser::regex::Matches`.
+pub fn match_captures(input_chars: &[char], regex: &Regex) -> Vec<Captures> {
+    regex
+        .find_iter(input_chars)
+        .filter(|((start, end), _)| start < end)
+        .collect()
+}
+
+/// `-o`: prints every non-empty match in `line` on its own line, and returns
+/// whether the line matched at all.
+fn print_only_matching(
+    out: &mut dyn Write,
+    line: &DecodedLine,
+    config: &Config,
+    filename: Option<&Path>,
+    position: LineOffset,
+) -> io::Result<bool> {
+    let mut matched = false;
+    for (span, groups) in config.regex.find_iter(line.text()) {
+        matched = true;
+        if span.0 < span.1 {
+            let mut output = Vec::new();
+            push_match(&mut output, line, span, &groups, config.colors.as_ref());
+            print_line(
+                out,
+                config,
+                filename,
+                ':',
+                position.of(line, span.0),
+                &output,
+            )?;
         }
     }
-    found
+    Ok(matched)
 }
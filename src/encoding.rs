@@ -0,0 +1,277 @@
+use std::io::{self, Read};
+
+use crate::reader::CHUNK_SIZE;
+
+/// A text encoding input can be transcoded from, named with `--encoding` or
+/// found from a byte order mark. Input is searched as UTF-8 whatever its
+/// encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Passed through as is, invalid sequences included (the default)
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1: every byte is the code point of the same value
+    Latin1,
+    /// Latin-1 with printable characters in place of most C1 controls
+    Windows1252,
+}
+
+/// The byte order marks that give away an input's encoding.
+const BOMS: [(&[u8], Encoding); 3] = [
+    (b"\xEF\xBB\xBF", Encoding::Utf8),
+    (b"\xFF\xFE", Encoding::Utf16Le),
+    (b"\xFE\xFF", Encoding::Utf16Be),
+];
+
+/// What Windows-1252 maps the bytes 0x80..=0x9F to; the five it leaves
+/// undefined keep their Latin-1 code point, as in the WHATWG encoding standard.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+fn push_char(out: &mut Vec<u8>, c: char) {
+    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+impl Encoding {
+    /// Parses an `--encoding` name, ignoring case and `-` or `_`.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        let name: String = name
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        match name.as_str() {
+            "utf8" => Some(Encoding::Utf8),
+            "utf16le" => Some(Encoding::Utf16Le),
+            "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "iso88591" => Some(Encoding::Latin1),
+            "windows1252" | "cp1252" => Some(Encoding::Windows1252),
+            _ => None,
+        }
+    }
+
+    /// The UTF-16 code unit in the two bytes `pair`.
+    fn unit(self, pair: [u8; 2]) -> u16 {
+        if self == Encoding::Utf16Le {
+            u16::from_le_bytes(pair)
+        } else {
+            u16::from_be_bytes(pair)
+        }
+    }
+
+    /// Transcodes as much of `input` as can be to UTF-8 in `out`, and returns
+    /// how many bytes it used up. Only a UTF-16 code unit or surrogate pair cut
+    /// off at the end is left, unless `last` says no more input is coming; then
+    /// it's replaced with U+FFFD, as are unpaired surrogates.
+    fn decode(self, input: &[u8], out: &mut Vec<u8>, last: bool) -> usize {
+        match self {
+            Encoding::Utf8 => {
+                out.extend_from_slice(input);
+                input.len()
+            }
+            Encoding::Latin1 => {
+                for &byte in input {
+                    push_char(out, byte as char);
+                }
+                input.len()
+            }
+            Encoding::Windows1252 => {
+                for &byte in input {
+                    let c = match byte {
+                        0x80..=0x9F => WINDOWS_1252_HIGH[byte as usize - 0x80],
+                        _ => byte as char,
+                    };
+                    push_char(out, c);
+                }
+                input.len()
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let mut pos = 0;
+                while pos + 2 <= input.len() {
+                    let unit = self.unit([input[pos], input[pos + 1]]);
+                    if (0xD800..0xDC00).contains(&unit) {
+                        if pos + 4 > input.len() && !last {
+                            break;
+                        }
+                        let low = input
+                            .get(pos + 2..pos + 4)
+                            .map(|pair| self.unit([pair[0], pair[1]]))
+                            .filter(|low| (0xDC00..0xE000).contains(low));
+                        if let Some(low) = low {
+                            let code = 0x10000
+                                + ((u32::from(unit) - 0xD800) << 10)
+                                + (u32::from(low) - 0xDC00);
+                            push_char(out, char::from_u32(code).unwrap_or('\u{FFFD}'));
+                            pos += 4;
+                            continue;
+                        }
+                    }
+                    push_char(out, char::from_u32(unit.into()).unwrap_or('\u{FFFD}'));
+                    pos += 2;
+                }
+                if last && pos < input.len() {
+                    push_char(out, '\u{FFFD}');
+                    pos = input.len();
+                }
+                pos
+            }
+        }
+    }
+
+    /// How many bytes `text`, UTF-8 decoded from this encoding, took up in the
+    /// original input.
+    pub fn encoded_len(self, text: &[u8]) -> usize {
+        match self {
+            Encoding::Utf8 => text.len(),
+            Encoding::Latin1 | Encoding::Windows1252 => {
+                String::from_utf8_lossy(text).chars().count()
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => String::from_utf8_lossy(text)
+                .chars()
+                .map(|c| 2 * c.len_utf16())
+                .sum(),
+        }
+    }
+}
+
+/// How an input was decoded: its encoding and the length of the byte order
+/// mark skipped at its start, to map offsets back to the original bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decoding {
+    pub encoding: Encoding,
+    pub bom_len: usize,
+}
+
+impl Default for Decoding {
+    fn default() -> Self {
+        Decoding {
+            encoding: Encoding::Utf8,
+            bom_len: 0,
+        }
+    }
+}
+
+impl Decoding {
+    /// The decoding for input starting with `start`. A byte order mark decides
+    /// unless `--encoding` named one (`forced`), and is skipped if it belongs to
+    /// the encoding used; input without either is UTF-8.
+    pub fn detect(start: &[u8], forced: Option<Encoding>) -> Decoding {
+        let bom = BOMS.iter().find(|(bom, encoding)| {
+            start.starts_with(bom) && forced.is_none_or(|f| f == *encoding)
+        });
+        match (bom, forced) {
+            (Some((bom, encoding)), _) => Decoding {
+                encoding: *encoding,
+                bom_len: bom.len(),
+            },
+            (None, Some(encoding)) => Decoding {
+                encoding,
+                bom_len: 0,
+            },
+            (None, None) => Decoding::default(),
+        }
+    }
+
+    /// Byte length in the original input of `text`, part of the decoded input.
+    pub fn original_len(&self, text: &[u8]) -> usize {
+        self.encoding.encoded_len(text)
+    }
+}
+
+/// A reader that transcodes its input to UTF-8 on the fly, see `Decoding`.
+/// UTF-8 input only loses its byte order mark and is otherwise read straight
+/// through.
+pub struct Decoder<R> {
+    inner: R,
+    decoding: Decoding,
+    /// Bytes read but not decoded yet: the start sniffed for a byte order mark,
+    /// or a UTF-16 code unit cut off at the end of a chunk
+    pending: Vec<u8>,
+    decoded: Vec<u8>,
+    /// How much of `decoded` was handed out already
+    pos: usize,
+    eof: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    /// Reads just enough of `inner` to tell whether it starts with a byte order
+    /// mark, so a line that arrives on its own still gets through right away.
+    pub fn new(mut inner: R, forced: Option<Encoding>) -> io::Result<Decoder<R>> {
+        let mut pending = Vec::new();
+        let mut eof = false;
+        // Only input that could still turn out to start with a mark is waited for
+        while BOMS
+            .iter()
+            .any(|(bom, _)| bom.len() > pending.len() && bom.starts_with(&pending))
+        {
+            let mut byte = [0];
+            match inner.read(&mut byte) {
+                Ok(0) => {
+                    eof = true;
+                    break;
+                }
+                Ok(_) => pending.push(byte[0]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let decoding = Decoding::detect(&pending, forced);
+        pending.drain(..decoding.bom_len);
+        Ok(Decoder {
+            inner,
+            decoding,
+            pending,
+            decoded: Vec::new(),
+            pos: 0,
+            eof,
+        })
+    }
+
+    pub fn decoding(&self) -> Decoding {
+        self.decoding
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.pos < self.decoded.len() {
+                let n = buf.len().min(self.decoded.len() - self.pos);
+                buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            let encoding = self.decoding.encoding;
+            if encoding == Encoding::Utf8 && self.pending.is_empty() && !self.eof {
+                return self.inner.read(buf);
+            }
+
+            // Read on before handing out what's pending, so the first read
+            // returns a whole chunk: binary and `--crlf=auto` detection look at it
+            if !self.eof {
+                let start = self.pending.len();
+                self.pending.resize(start + CHUNK_SIZE, 0);
+                let read = self.inner.read(&mut self.pending[start..]);
+                self.pending.truncate(start + *read.as_ref().unwrap_or(&0));
+                match read {
+                    Ok(0) => self.eof = true,
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            self.decoded.clear();
+            self.pos = 0;
+            let used = encoding.decode(&self.pending, &mut self.decoded, self.eof);
+            self.pending.drain(..used);
+            if self.decoded.is_empty() && self.eof {
+                return Ok(0);
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::glob::Glob;
+
+/// Built-in file types and the file name globs that make them up.
+const BUILTIN_TYPES: &[(&str, &[&str])] = &[
+    ("c", &["*.c", "*.h"]),
+    (
+        "config",
+        &[
+            "*.cfg", "*.conf", "*.ini", "*.json", "*.toml", "*.yaml", "*.yml",
+        ],
+    ),
+    (
+        "cpp",
+        &["*.cc", "*.cpp", "*.cxx", "*.h", "*.hh", "*.hpp", "*.hxx"],
+    ),
+    ("css", &["*.css", "*.less", "*.sass", "*.scss"]),
+    ("docker", &["Dockerfile", "*.dockerfile"]),
+    ("go", &["*.go"]),
+    ("html", &["*.htm", "*.html"]),
+    ("ini", &["*.ini"]),
+    ("java", &["*.java"]),
+    ("js", &["*.cjs", "*.js", "*.jsx", "*.mjs"]),
+    ("json", &["*.json"]),
+    ("make", &["GNUmakefile", "Makefile", "makefile", "*.mk"]),
+    ("markdown", &["*.markdown", "*.md"]),
+    ("md", &["*.markdown", "*.md"]),
+    ("py", &["*.py", "*.pyi"]),
+    ("python", &["*.py", "*.pyi"]),
+    ("ruby", &["Gemfile", "Rakefile", "*.rb"]),
+    ("rust", &["*.rs"]),
+    ("sh", &["*.bash", "*.sh", "*.zsh"]),
+    ("toml", &["*.toml"]),
+    ("ts", &["*.cts", "*.mts", "*.ts", "*.tsx"]),
+    ("txt", &["*.txt"]),
+    ("xml", &["*.xml"]),
+    ("yaml", &["*.yaml", "*.yml"]),
+];
+
+/// Named file types, as used by `-t`/`-T`: the built-ins plus any added with
+/// `--type-add`.
+pub struct TypeRegistry {
+    types: BTreeMap<String, Vec<String>>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        let types = BUILTIN_TYPES
+            .iter()
+            .map(|(name, globs)| {
+                let globs = globs.iter().map(|g| g.to_string()).collect();
+                (name.to_string(), globs)
+            })
+            .collect();
+        TypeRegistry { types }
+    }
+
+    /// Adds the glob of a `--type-add name:glob` spec to `name`, creating the
+    /// type if it doesn't exist yet.
+    pub fn add(&mut self, spec: &str) -> Result<(), String> {
+        match spec.split_once(':') {
+            Some((name, glob)) if !name.is_empty() && !glob.is_empty() => {
+                self.types
+                    .entry(name.to_string())
+                    .or_default()
+                    .push(glob.to_string());
+                Ok(())
+            }
+            _ => Err(format!(
+                "invalid argument '{}' for --type-add, expected NAME:GLOB",
+                spec
+            )),
+        }
+    }
+
+    /// One `name: glob, glob` line per type, sorted by name, for `--type-list`.
+    pub fn list(&self) -> Vec<String> {
+        self.types
+            .iter()
+            .map(|(name, globs)| format!("{}: {}", name, globs.join(", ")))
+            .collect()
+    }
+
+    /// Compiles the filter for the `-t` types in `selected` and the `-T` types in
+    /// `negated`.
+    pub fn filter(&self, selected: &[String], negated: &[String]) -> Result<TypeFilter, String> {
+        let globs = |names: &[String]| -> Result<Vec<Glob>, String> {
+            let mut globs = Vec::new();
+            for name in names {
+                let patterns = self
+                    .types
+                    .get(name)
+                    .ok_or_else(|| format!("unrecognized file type: {}", name))?;
+                globs.extend(patterns.iter().map(|p| Glob::new(p)));
+            }
+            Ok(globs)
+        };
+        Ok(TypeFilter {
+            selected: globs(selected)?,
+            negated: globs(negated)?,
+        })
+    }
+}
+
+/// The `-t`/`-T` file type filter of a recursive search.
+#[derive(Debug, Default)]
+pub struct TypeFilter {
+    selected: Vec<Glob>,
+    negated: Vec<Glob>,
+}
+
+impl TypeFilter {
+    /// Whether a file found while walking is of a selected type (if any type was
+    /// selected) and of none of the negated ones.
+    pub fn allows(&self, path: &Path) -> bool {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+        (self.selected.is_empty() || self.selected.iter().any(|g| g.matches(&name)))
+            && !self.negated.iter().any(|g| g.matches(&name))
+    }
+}
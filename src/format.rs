@@ -0,0 +1,133 @@
+use std::io::{self, Write};
+
+use crate::color::Colors;
+use crate::parser::regex::Regex;
+
+/// One piece of a `--format` template.
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    Path,
+    Line,
+    Column,
+    Offset,
+    /// `{0}` for the whole match, `{N}` or `{name}` for a capture group
+    Group(usize),
+}
+
+/// What the placeholders of a `--format` template stand for in one match.
+pub struct FormatMatch<'a> {
+    pub path: &'a [u8],
+    pub line_number: usize,
+    /// 1-based byte column of the start of the match
+    pub column: usize,
+    /// Byte offset of the start of the match in the file
+    pub offset: usize,
+    pub text: &'a [u8],
+    pub captures: &'a [Option<&'a [u8]>],
+}
+
+/// A parsed `--format` template, printed once per match instead of the line.
+///
+/// `{path}`, `{line}`, `{column}` and `{offset}` stand for where the match was
+/// found, `{0}` for the match itself and `{1}`, `{2}`, ... or `{name}` for its
+/// capture groups; groups that didn't take part print nothing. `\n`, `\t`, `\r`,
+/// `\\`, `\{` and `\}` are escapes, and every match ends with a newline.
+#[derive(Debug, Clone)]
+pub struct Format {
+    parts: Vec<Part>,
+}
+
+impl Format {
+    /// Parses `text`, checking its group references against `regex`.
+    pub fn new(text: &str, regex: &Regex) -> Result<Format, String> {
+        let names = regex.group_names();
+        let resolve = |placeholder: &str| match placeholder {
+            "path" => Ok(Part::Path),
+            "line" => Ok(Part::Line),
+            "column" => Ok(Part::Column),
+            "offset" => Ok(Part::Offset),
+            _ => match placeholder.parse::<usize>() {
+                Ok(number) if number <= names.len() => Ok(Part::Group(number)),
+                Ok(_) => Err(format!("no group {{{}}} in the pattern", placeholder)),
+                Err(_) => names
+                    .iter()
+                    .position(|name| name.as_deref() == Some(placeholder))
+                    .map(|index| Part::Group(index + 1))
+                    .ok_or_else(|| format!("unknown placeholder {{{}}} in --format", placeholder)),
+            },
+        };
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => literal.push('\n'),
+                    Some('t') => literal.push('\t'),
+                    Some('r') => literal.push('\r'),
+                    Some(c @ ('\\' | '{' | '}')) => literal.push(c),
+                    Some(c) => {
+                        literal.push('\\');
+                        literal.push(c);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        return Err("unclosed '{' in --format".to_string());
+                    };
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(resolve(&rest[..end])?);
+                    chars = rest[end + 1..].chars();
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Format { parts })
+    }
+
+    /// Writes the template for one match, followed by a newline. With `colors`,
+    /// paths, line numbers, the match and its groups are colored as in normal
+    /// output.
+    pub fn write(
+        &self,
+        out: &mut dyn Write,
+        found: &FormatMatch,
+        colors: Option<&Colors>,
+    ) -> io::Result<()> {
+        let mut output = Vec::new();
+        let mut push = |sgr: Option<&str>, text: &[u8]| match sgr {
+            Some(sgr) => Colors::paint(&mut output, sgr, text),
+            None => output.extend_from_slice(text),
+        };
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => push(None, text.as_bytes()),
+                Part::Path => push(colors.map(|c| c.filename.as_str()), found.path),
+                Part::Line => push(
+                    colors.map(|c| c.line_number.as_str()),
+                    found.line_number.to_string().as_bytes(),
+                ),
+                Part::Column => push(None, found.column.to_string().as_bytes()),
+                Part::Offset => push(None, found.offset.to_string().as_bytes()),
+                Part::Group(0) => push(colors.map(|c| c.selected_match.as_str()), found.text),
+                Part::Group(number) => {
+                    if let Some(Some(text)) = found.captures.get(number - 1) {
+                        push(colors.map(|c| c.group(*number)), text);
+                    }
+                }
+            }
+        }
+        output.push(b'\n');
+        out.write_all(&output)
+    }
+}
@@ -0,0 +1,228 @@
+use std::path::Path;
+
+/// One element of a compiled glob.
+#[derive(Debug, Clone)]
+enum GlobToken {
+    /// A character that must match exactly
+    Literal(char),
+    /// `?`: any single character except `/`
+    AnyChar,
+    /// `*`: any run of characters except `/`
+    Star,
+    /// `**` as a whole path segment at the end: anything, including `/`
+    DoubleStar,
+    /// `**/`: zero or more whole directories
+    DoubleStarSlash,
+    /// `[...]` / `[!...]`: one character (never `/`) from a set of ranges
+    Class(bool, Vec<(char, char)>),
+}
+
+/// A shell-style glob matched against `/`-separated paths.
+///
+/// `*`, `?` and classes never match `/`, so they stay within a path segment,
+/// while `**` as a whole segment matches across directories: `**/x` matches `x`
+/// at any depth, `a/**/b` matches `a/b`, `a/x/b`, `a/x/y/b`, ... and `a/**`
+/// matches everything inside `a`. `{a,b}` matches either alternative and may
+/// nest. A backslash escapes the next character.
+#[derive(Debug, Clone)]
+pub struct Glob {
+    /// One token list per alternative the braces expand to
+    alternatives: Vec<Vec<GlobToken>>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Glob {
+        let chars: Vec<char> = pattern.chars().collect();
+        Glob {
+            alternatives: expand_braces(&chars).iter().map(|p| tokenize(p)).collect(),
+        }
+    }
+
+    /// Whether the glob matches all of `path`.
+    pub fn matches(&self, path: &str) -> bool {
+        let chars: Vec<char> = path.chars().collect();
+        self.alternatives
+            .iter()
+            .any(|tokens| matches_from(tokens, &chars))
+    }
+
+    /// Whether the glob matches `path` or one of its trailing parts starting at a
+    /// component, so `*.rs` matches `src/main.rs` and `src/*.rs` matches
+    /// `./src/main.rs`.
+    pub fn matches_path(&self, path: &Path) -> bool {
+        let components: Vec<_> = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect();
+        (0..components.len()).any(|start| self.matches(&components[start..].join("/")))
+    }
+}
+
+/// Expands the first top-level `{...}` group of `pattern` into one pattern per
+/// alternative, recursively. Unclosed braces are kept as they are.
+fn expand_braces(pattern: &[char]) -> Vec<Vec<char>> {
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            '\\' => i += 2,
+            '{' => {
+                let mut depth = 0;
+                let mut splits = vec![i];
+                let mut j = i;
+                while j < pattern.len() {
+                    match pattern[j] {
+                        '\\' => j += 1,
+                        '{' => depth += 1,
+                        ',' if depth == 1 => splits.push(j),
+                        '}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    j += 1;
+                }
+                if j >= pattern.len() {
+                    break;
+                }
+                splits.push(j);
+
+                let (prefix, suffix) = (&pattern[..i], &pattern[j + 1..]);
+                return splits
+                    .windows(2)
+                    .flat_map(|w| {
+                        let mut expanded = prefix.to_vec();
+                        expanded.extend_from_slice(&pattern[w[0] + 1..w[1]]);
+                        expanded.extend_from_slice(suffix);
+                        expand_braces(&expanded)
+                    })
+                    .collect();
+            }
+            _ => i += 1,
+        }
+    }
+    vec![pattern.to_vec()]
+}
+
+/// Compiles one pattern whose braces have been expanded.
+fn tokenize(chars: &[char]) -> Vec<GlobToken> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                tokens.push(GlobToken::Literal(chars[i + 1]));
+                i += 2;
+            }
+            '?' => {
+                tokens.push(GlobToken::AnyChar);
+                i += 1;
+            }
+            '*' => {
+                let mut end = i;
+                while end < chars.len() && chars[end] == '*' {
+                    end += 1;
+                }
+                let starts_segment = i == 0 || chars[i - 1] == '/';
+                let ends_segment = end == chars.len() || chars[end] == '/';
+                if end - i == 2 && starts_segment && ends_segment {
+                    if end < chars.len() {
+                        tokens.push(GlobToken::DoubleStarSlash);
+                        end += 1;
+                    } else {
+                        tokens.push(GlobToken::DoubleStar);
+                    }
+                } else {
+                    // Other runs of asterisks are just a regular `*`
+                    tokens.push(GlobToken::Star);
+                }
+                i = end;
+            }
+            '[' => match parse_class(chars, i) {
+                Some((token, next)) => {
+                    tokens.push(token);
+                    i = next;
+                }
+                None => {
+                    tokens.push(GlobToken::Literal('['));
+                    i += 1;
+                }
+            },
+            c => {
+                tokens.push(GlobToken::Literal(c));
+                i += 1;
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Parses the class starting at `chars[start] == '['`, returning it and the index
+/// after its closing `]`, or `None` if it is never closed.
+fn parse_class(chars: &[char], start: usize) -> Option<(GlobToken, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    while i < chars.len() {
+        let c = chars[i];
+        // A `]` right after the opening bracket is a literal
+        if c == ']' && !first {
+            return Some((GlobToken::Class(negated, ranges), i + 1));
+        }
+        first = false;
+
+        let (low, next) = if c == '\\' && i + 1 < chars.len() {
+            (chars[i + 1], i + 2)
+        } else {
+            (c, i + 1)
+        };
+        if chars.get(next) == Some(&'-') && chars.get(next + 1).is_some_and(|&c| c != ']') {
+            ranges.push((low, chars[next + 1]));
+            i = next + 2;
+        } else {
+            ranges.push((low, low));
+            i = next;
+        }
+    }
+    None
+}
+
+fn matches_from(tokens: &[GlobToken], path: &[char]) -> bool {
+    let Some(token) = tokens.first() else {
+        return path.is_empty();
+    };
+    let rest = &tokens[1..];
+
+    match token {
+        GlobToken::Literal(c) => path.first() == Some(c) && matches_from(rest, &path[1..]),
+        GlobToken::AnyChar => {
+            path.first().is_some_and(|&c| c != '/') && matches_from(rest, &path[1..])
+        }
+        GlobToken::Class(negated, ranges) => {
+            path.first().is_some_and(|&c| {
+                c != '/' && ranges.iter().any(|&(low, high)| low <= c && c <= high) != *negated
+            }) && matches_from(rest, &path[1..])
+        }
+        GlobToken::Star => {
+            let segment_len = path.iter().position(|&c| c == '/').unwrap_or(path.len());
+            (0..=segment_len).any(|len| matches_from(rest, &path[len..]))
+        }
+        GlobToken::DoubleStar => (0..=path.len()).any(|len| matches_from(rest, &path[len..])),
+        GlobToken::DoubleStarSlash => {
+            matches_from(rest, path)
+                || path
+                    .iter()
+                    .enumerate()
+                    .any(|(i, &c)| c == '/' && matches_from(rest, &path[i + 1..]))
+        }
+    }
+}
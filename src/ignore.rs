@@ -0,0 +1,252 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::glob::Glob;
+
+/// Ignore files read in every directory of a recursive search, lowest
+/// precedence first: a `.rgrepignore` rule overrides a `.gitignore` one.
+const IGNORE_FILES: [&str; 3] = [".gitignore", ".ignore", ".rgrepignore"];
+
+/// One pattern line of an ignore file.
+struct Rule {
+    glob: Glob,
+    /// `!pattern`: re-includes what an earlier rule ignored
+    negated: bool,
+    /// `pattern/`: only matches directories
+    dir_only: bool,
+    /// Patterns with a `/` before their end match the path relative to the
+    /// ignore file's directory, others match the file name at any depth
+    anchored: bool,
+}
+
+impl Rule {
+    /// Parses one line of gitignore syntax; blank lines and comments give `None`.
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        // Trailing spaces are dropped unless escaped with a backslash
+        let mut end = line.len();
+        while line[..end].ends_with(' ') && !line[..end - 1].ends_with('\\') {
+            end -= 1;
+        }
+        let mut pattern = &line[..end];
+
+        let negated = pattern.starts_with('!');
+        if negated {
+            pattern = &pattern[1..];
+        }
+        let dir_only = pattern.ends_with('/');
+        if dir_only {
+            pattern = &pattern[..pattern.len() - 1];
+        }
+        let anchored = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        if pattern.is_empty() {
+            return None;
+        }
+
+        Some(Rule {
+            glob: Glob::new(pattern),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, relative: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            self.glob.matches(relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            self.glob.matches(name)
+        }
+    }
+}
+
+fn parse_rules(path: &Path) -> Vec<Rule> {
+    fs::read_to_string(path)
+        .map(|text| text.lines().filter_map(Rule::parse).collect())
+        .unwrap_or_default()
+}
+
+/// The rules of all the ignore files in `dir`, in `IGNORE_FILES` order.
+fn read_ignore_files(dir: &Path) -> Vec<Rule> {
+    IGNORE_FILES
+        .iter()
+        .flat_map(|name| parse_rules(&dir.join(name)))
+        .collect()
+}
+
+/// The directories above `dir` up to the top of the git repository it's in
+/// (the nearest one holding `.git`), top first, each with the path from it
+/// down to `dir`. Empty when `dir` is the top or isn't in a repository.
+fn repository_ancestors(dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let Ok(dir) = fs::canonicalize(dir) else {
+        return Vec::new();
+    };
+    if dir.join(".git").exists() {
+        return Vec::new();
+    }
+    let mut ancestors = Vec::new();
+    for ancestor in dir.ancestors().skip(1) {
+        let prefix = dir.strip_prefix(ancestor).unwrap_or(&dir).to_path_buf();
+        ancestors.push((ancestor.to_path_buf(), prefix));
+        if ancestor.join(".git").exists() {
+            ancestors.reverse();
+            return ancestors;
+        }
+    }
+    Vec::new()
+}
+
+/// The ignore rules in effect inside one directory of the walk: those of its
+/// own ignore files on top of the ones inherited from the directories above.
+///
+/// Rules of deeper directories take precedence, and within a directory the last
+/// matching rule wins, as in git. Stacks are shared between sibling directories
+/// and walker threads, so each level only holds the rules it added.
+pub struct IgnoreStack {
+    /// Where the walk's paths start from for this level's rules to apply
+    dir: PathBuf,
+    /// Where `dir` is inside the directory the rules came from: empty, unless
+    /// they came from above the directory the walk started in
+    prefix: PathBuf,
+    rules: Vec<Rule>,
+    parent: Option<Arc<IgnoreStack>>,
+}
+
+impl IgnoreStack {
+    /// The stack for a directory given on the command line, or an empty one
+    /// with `no_ignore`. It holds the global excludes file and, when `dir` is
+    /// inside a git repository, the ignore files of the directories between
+    /// the top of the repository and `dir`, each anchored where it was found.
+    pub fn root(dir: &Path, no_ignore: bool) -> Arc<IgnoreStack> {
+        let level = |prefix: PathBuf, rules, parent| {
+            Arc::new(IgnoreStack {
+                dir: dir.to_path_buf(),
+                prefix,
+                rules,
+                parent,
+            })
+        };
+        if no_ignore {
+            return level(PathBuf::new(), Vec::new(), None);
+        }
+
+        let ancestors = repository_ancestors(dir);
+        // Like git, anchor the global excludes to the top of the repository
+        let top = ancestors
+            .first()
+            .map(|(_, prefix)| prefix.clone())
+            .unwrap_or_default();
+        let global = global_excludes_file()
+            .map(|file| parse_rules(&file))
+            .unwrap_or_default();
+        let mut stack = level(top, global, None);
+        for (ancestor, prefix) in ancestors {
+            let rules = read_ignore_files(&ancestor);
+            if !rules.is_empty() {
+                stack = level(prefix, rules, Some(stack));
+            }
+        }
+        stack
+    }
+
+    /// The stack for the entries of `dir`: this one plus the rules of any ignore
+    /// files in `dir`.
+    pub fn child(self: &Arc<Self>, dir: &Path) -> Arc<IgnoreStack> {
+        let rules = read_ignore_files(dir);
+        if rules.is_empty() {
+            return Arc::clone(self);
+        }
+        Arc::new(IgnoreStack {
+            dir: dir.to_path_buf(),
+            prefix: PathBuf::new(),
+            rules,
+            parent: Some(Arc::clone(self)),
+        })
+    }
+
+    /// Whether `path`, found while walking below this stack's directory, should
+    /// be left out of the search.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut level = Some(self);
+        while let Some(stack) = level {
+            if let Ok(relative) = path.strip_prefix(&stack.dir) {
+                let relative = stack
+                    .prefix
+                    .join(relative)
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                if let Some(rule) = stack
+                    .rules
+                    .iter()
+                    .rev()
+                    .find(|r| r.matches(&relative, is_dir))
+                {
+                    return !rule.negated;
+                }
+            }
+            level = stack.parent.as_deref();
+        }
+        false
+    }
+}
+
+/// The user's global ignore file: `core.excludesFile` from their git config,
+/// or git's default of `$XDG_CONFIG_HOME/git/ignore`.
+fn global_excludes_file() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|h| h.join(".config")));
+
+    let configs = [
+        home.as_ref().map(|h| h.join(".gitconfig")),
+        config_home.as_ref().map(|c| c.join("git/config")),
+    ];
+    for config in configs.iter().flatten() {
+        if let Some(file) = fs::read_to_string(config)
+            .ok()
+            .and_then(|text| excludes_file_setting(&text))
+        {
+            return Some(match (file.strip_prefix("~/"), &home) {
+                (Some(rest), Some(home)) => home.join(rest),
+                _ => PathBuf::from(file),
+            });
+        }
+    }
+
+    config_home.map(|c| c.join("git/ignore"))
+}
+
+/// Finds `excludesFile` in the `[core]` section of a git config file.
+fn excludes_file_setting(config: &str) -> Option<String> {
+    let mut in_core = false;
+    for line in config.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_core = line
+                .trim_matches(['[', ']'])
+                .trim()
+                .eq_ignore_ascii_case("core");
+        } else if in_core
+            && let Some((key, value)) = line.split_once('=')
+            && key.trim().eq_ignore_ascii_case("excludesfile")
+        {
+            return Some(value.trim().trim_matches('"').to_string());
+        }
+    }
+    None
+}
@@ -0,0 +1,272 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::core::{self, Captures, Config, FileMatches, SearchError};
+use crate::encoding::Decoding;
+use crate::parser::input::DecodedLine;
+use crate::reader::LineSource;
+
+/// Totals over every file searched, for the final `summary` event.
+static SEARCHES: AtomicUsize = AtomicUsize::new(0);
+static SEARCHES_WITH_MATCH: AtomicUsize = AtomicUsize::new(0);
+static MATCHED_LINES: AtomicUsize = AtomicUsize::new(0);
+static MATCHES: AtomicUsize = AtomicUsize::new(0);
+static BYTES_SEARCHED: AtomicUsize = AtomicUsize::new(0);
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Appends `text` as a JSON string literal.
+fn push_string(json: &mut String, text: &str) {
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// Appends arbitrary bytes as `{"text":"..."}` when they are valid UTF-8 and as
+/// `{"bytes":"<base64>"}` otherwise, so paths and lines survive unchanged.
+fn push_data(json: &mut String, bytes: &[u8]) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => {
+            json.push_str("{\"text\":");
+            push_string(json, text);
+        }
+        Err(_) => {
+            json.push_str("{\"bytes\":\"");
+            json.push_str(&base64(bytes));
+            json.push('"');
+        }
+    }
+    json.push('}');
+}
+
+/// Writes the events of one file, starting with `begin` right before its first
+/// line event, so files without any output stay silent.
+struct FileEvents<'a> {
+    out: &'a mut dyn Write,
+    path: Option<&'a Path>,
+    begun: bool,
+    /// How the file was decoded, for offsets in its original bytes
+    decoding: Decoding,
+}
+
+impl FileEvents<'_> {
+    fn begin(&mut self) -> io::Result<()> {
+        if !self.begun {
+            self.begun = true;
+            self.event("begin", "")?;
+        }
+        Ok(())
+    }
+
+    fn event(&mut self, kind: &str, data: &str) -> io::Result<()> {
+        let mut json = format!("{{\"type\":\"{}\",\"data\":{{", kind);
+        if let Some(path) = self.path {
+            json.push_str("\"path\":");
+            push_data(&mut json, core::path_bytes(path));
+            if !data.is_empty() {
+                json.push(',');
+            }
+        }
+        json.push_str(data);
+        json.push_str("}}");
+        writeln!(self.out, "{}", json)
+    }
+
+    /// A `match` or `context` event for one line, with the matches in `found`.
+    /// Offsets, of the line in the file and of the matches in the line, are
+    /// those of the original bytes when the file was transcoded.
+    fn line(
+        &mut self,
+        kind: &str,
+        line_index: usize,
+        offset: usize,
+        line: &DecodedLine,
+        found: &[Captures],
+    ) -> io::Result<()> {
+        self.begin()?;
+
+        let mut data = String::from("\"lines\":");
+        push_data(&mut data, line.bytes());
+        data.push_str(&format!(
+            ",\"line_number\":{},\"absolute_offset\":{},\"submatches\":[",
+            line_index + 1,
+            offset
+        ));
+        for (i, ((start, end), captures)) in found.iter().enumerate() {
+            if i > 0 {
+                data.push(',');
+            }
+            data.push_str("{\"match\":");
+            push_data(&mut data, line.slice(*start, *end));
+            data.push_str(&format!(
+                ",\"start\":{},\"end\":{},\"captures\":[",
+                self.decoding.original_len(line.slice(0, *start)),
+                self.decoding.original_len(line.slice(0, *end))
+            ));
+            for (j, capture) in captures.iter().enumerate() {
+                if j > 0 {
+                    data.push(',');
+                }
+                match capture {
+                    Some((start, end)) => push_data(&mut data, line.slice(*start, *end)),
+                    None => data.push_str("null"),
+                }
+            }
+            data.push_str("]}");
+        }
+        data.push(']');
+
+        self.event(kind, &data)
+    }
+
+    fn context(&mut self, line_index: usize, offset: usize, line: &[u8]) -> io::Result<()> {
+        self.line("context", line_index, offset, &DecodedLine::new(line), &[])
+    }
+}
+
+/// `core::search_file` for `--json`: every matching line becomes a `match` event
+/// with its submatches and capture groups, and `-A`/`-B`/`-C` lines become
+/// `context` events. A file with any events ends with an `end` event holding its
+/// stats.
+///
+/// As in text output, a binary file's lines aren't shown unless `-a` is given:
+/// its first match ends the search, and only its `begin` and `end` events are
+/// written.
+pub fn search_file<S: LineSource>(
+    mut reader: S,
+    config: &Config,
+    path: Option<&Path>,
+    limit: Option<usize>,
+    binary: bool,
+    crlf: bool,
+    out: &mut dyn Write,
+) -> Result<FileMatches, SearchError> {
+    let decoding = reader.decoding();
+    let mut events = FileEvents {
+        out,
+        path,
+        begun: false,
+        decoding,
+    };
+    let mut before: VecDeque<(usize, usize, Vec<u8>)> = VecDeque::new();
+    let mut after_left = 0;
+    let mut matched_lines = 0;
+    let mut matches = 0;
+    let terminator_len = decoding.original_len(&[config.line_terminator()]);
+    let mut offset = decoding.bom_len;
+    let mut next_index = 0;
+
+    while let Some(bytes) = reader.next_line().map_err(SearchError::Input)? {
+        let (line_index, line_offset) = (next_index, offset);
+        next_index += 1;
+        offset += decoding.original_len(bytes) + terminator_len;
+
+        if limit.is_some_and(|limit| matched_lines >= limit) {
+            // Past the last allowed match, only trailing context is left
+            if after_left == 0 {
+                break;
+            }
+            after_left -= 1;
+            events.context(line_index, line_offset, bytes)?;
+            continue;
+        }
+
+        let line = DecodedLine::new(bytes).with_crlf(crlf);
+        if binary {
+            if core::match_pattern(&line, config) {
+                events.begin()?;
+                matched_lines += 1;
+                matches += core::match_captures(line.text(), &config.regex).len();
+                break;
+            }
+            continue;
+        }
+        if core::match_pattern(&line, config) {
+            for (index, line_offset, line) in before.drain(..) {
+                events.context(index, line_offset, &line)?;
+            }
+            let found = core::match_captures(line.text(), &config.regex);
+            events.line("match", line_index, line_offset, &line, &found)?;
+            matched_lines += 1;
+            matches += found.len();
+            after_left = config.after_context;
+        } else if after_left > 0 {
+            after_left -= 1;
+            events.context(line_index, line_offset, bytes)?;
+        } else if config.before_context > 0 {
+            if before.len() == config.before_context {
+                before.pop_front();
+            }
+            before.push_back((line_index, line_offset, bytes.to_vec()));
+        }
+    }
+
+    if events.begun {
+        events.event(
+            "end",
+            &format!(
+                "\"binary\":{},\"stats\":{{\"matched_lines\":{},\"matches\":{},\"bytes_searched\":{}}}",
+                binary, matched_lines, matches, offset
+            ),
+        )?;
+    }
+    SEARCHES.fetch_add(1, Ordering::Relaxed);
+    if matched_lines > 0 {
+        SEARCHES_WITH_MATCH.fetch_add(1, Ordering::Relaxed);
+    }
+    MATCHED_LINES.fetch_add(matched_lines, Ordering::Relaxed);
+    MATCHES.fetch_add(matches, Ordering::Relaxed);
+    BYTES_SEARCHED.fetch_add(offset, Ordering::Relaxed);
+
+    Ok(FileMatches {
+        count: matched_lines,
+        binary,
+    })
+}
+
+/// Writes the final `summary` event with the totals of the whole run.
+pub fn print_summary(out: &mut dyn Write, elapsed: Duration) -> io::Result<()> {
+    writeln!(
+        out,
+        "{{\"type\":\"summary\",\"data\":{{\"elapsed_total\":{{\"secs\":{},\"nanos\":{}}},\"stats\":{{\"searches\":{},\"searches_with_match\":{},\"matched_lines\":{},\"matches\":{},\"bytes_searched\":{}}}}}}}",
+        elapsed.as_secs(),
+        elapsed.subsec_nanos(),
+        SEARCHES.load(Ordering::Relaxed),
+        SEARCHES_WITH_MATCH.load(Ordering::Relaxed),
+        MATCHED_LINES.load(Ordering::Relaxed),
+        MATCHES.load(Ordering::Relaxed),
+        BYTES_SEARCHED.load(Ordering::Relaxed),
+    )
+}
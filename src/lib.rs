@@ -1,7 +1,9 @@
 use std::env;
 use std::error::Error;
-use std::path::Path;
-use std::process::Command;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 /// FROM ROOT: run_rgrep with pipeline `left_command | right_command`
 pub fn run_rgrep_from_root(left_command: &str, right_command: &str) -> bool {
@@ -17,18 +19,113 @@ pub fn run_rgrep_from_root(left_command: &str, right_command: &str) -> bool {
     // Pretty print stdout if any
     if !output.stdout.is_empty() {
         println!("rgrep output:");
-        print!("{}\n", String::from_utf8_lossy(&output.stdout));
+        println!("{}", String::from_utf8_lossy(&output.stdout));
     }
 
     // Print stderr if any (for debugging)
     if !output.stderr.is_empty() {
         eprintln!("rgrep stderr:");
-        eprint!("{}\n", String::from_utf8_lossy(&output.stderr));
+        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
     }
 
     output.status.success()
 }
 
+/// FROM ROOT: run_rgrep with pipeline `left_command | right_command`, returning its stdout
+pub fn run_rgrep_output_from_root(left_command: &str, right_command: &str) -> String {
+    find_rgrep_dir().expect("Failed to change to rgrep directory");
+
+    let pipeline = format!("{} | {}", left_command, right_command);
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(&pipeline)
+        .output()
+        .expect("Failed to execute pipeline");
+
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// A fresh, empty directory for one test's fixture files, removed again when
+/// dropped. Its name is unique to the test process and the call, so tests run in
+/// parallel never see each other's files.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "rgrep-test-{}-{}",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Left over from an earlier run that had the same process id
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).expect("Failed to create test directory");
+        TempDir { path }
+    }
+
+    /// A new directory holding `files`, each a path inside it and its contents.
+    pub fn with_files<P: AsRef<str>, C: AsRef<[u8]>>(files: &[(P, C)]) -> TempDir {
+        let dir = TempDir::new();
+        for (name, contents) in files {
+            dir.file(name.as_ref(), contents);
+        }
+        dir
+    }
+
+    /// Adds the symbolic links `links` inside, each a link name and its target.
+    #[cfg(unix)]
+    pub fn with_symlinks(self, links: &[(&str, &str)]) -> TempDir {
+        for (name, target) in links {
+            self.symlink(target, name);
+        }
+        self
+    }
+
+    /// The directory's path, to put in a command line.
+    pub fn path(&self) -> &str {
+        self.path.to_str().expect("Test directory path isn't UTF-8")
+    }
+
+    /// Creates the directory `name` inside, along with its parents.
+    pub fn dir(&self, name: &str) -> &TempDir {
+        fs::create_dir_all(self.path.join(name)).expect("Failed to create test directory");
+        self
+    }
+
+    /// Writes `contents` to the file `name` inside, creating its parents.
+    pub fn file(&self, name: &str, contents: impl AsRef<[u8]>) -> &TempDir {
+        let path = self.path.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("Failed to create test directory");
+        }
+        fs::write(path, contents).expect("Failed to write test file");
+        self
+    }
+
+    /// Creates the symbolic link `name` inside, pointing to `target`.
+    #[cfg(unix)]
+    pub fn symlink(&self, target: &str, name: &str) -> &TempDir {
+        std::os::unix::fs::symlink(target, self.path.join(name))
+            .expect("Failed to create test symlink");
+        self
+    }
+}
+
+impl Default for TempDir {
+    fn default() -> Self {
+        TempDir::new()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
 fn find_rgrep_dir() -> Result<(), Box<dyn Error>> {
     if Path::new("target").exists() {
         // Already in the rgrep directory
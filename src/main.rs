@@ -1,25 +1,142 @@
 use std::env;
+use std::fs;
 use std::io::{self, Read};
+use std::path::Path;
 use std::process;
+use std::thread;
+use std::time::Instant;
 
+use parser::regex::Syntax;
+
+mod color;
 mod core;
+mod encoding;
+mod filetypes;
+mod format;
+mod glob;
+mod ignore;
+mod json;
+mod mmap;
+mod multiline;
+mod parallel;
 mod parser;
+mod reader;
+mod replace;
+mod rewrite;
+
+fn usage(program: &str) -> String {
+    format!(
+        "Usage: {} [--color[=WHEN]] [--colors SPEC] [--json] [-r|-R] [--follow] [--max-depth NUM] [--one-file-system] [-s] [--no-ignore] [--hidden] [--include=GLOB] [--exclude=GLOB] [--exclude-dir=GLOB] [-t TYPE] [-T TYPE] [--type-add NAME:GLOB] [--type-list] [-a|-I|--binary-files=TYPE] [-o] [-U] [-z] [-Z] [--crlf[=WHEN]] [--encoding NAME] [-b] [--format TEMPLATE] [--replace TEMPLATE [--in-place|--dry-run] [--backup[=SUFFIX]]] [-c|-l|-L|-q] [-m NUM] [--[no-]mmap] [-j NUM] [--sort path] [-A NUM] [-B NUM] [-C NUM] [--files-from FILE] [-G|-E|-P] <pattern>|-e <pattern> [file...]",
+        program
+    )
+}
+
+/// Returns the value of a short option, either attached (`-A3`) or in the
+/// following argument (`-A 3`), along with the number of arguments consumed.
+fn short_value(args: &[String], i: usize) -> (Option<&str>, usize) {
+    if args[i].len() > 2 {
+        (Some(&args[i][2..]), 1)
+    } else {
+        (args.get(i + 1).map(|s| s.as_str()), 2)
+    }
+}
+
+/// Records the syntax chosen by `-G`, `-E` or `-P`; like GNU grep, giving two
+/// different ones is an error.
+fn select_syntax(syntax: &mut Option<Syntax>, chosen: Syntax) -> Result<(), String> {
+    match syntax {
+        Some(current) if *current != chosen => Err("conflicting matchers specified".to_string()),
+        _ => {
+            *syntax = Some(chosen);
+            Ok(())
+        }
+    }
+}
+
+/// Parses the `--encoding` argument.
+fn parse_encoding(name: &str) -> Result<encoding::Encoding, String> {
+    encoding::Encoding::from_name(name).ok_or_else(|| format!("unknown encoding '{}'", name))
+}
+
+/// Reads the paths listed in `path` (`-` for standard input) for `--files-from`:
+/// NUL-separated, as from `find -print0`, if there's any NUL byte, and one per
+/// line otherwise.
+fn read_file_list(path: &str) -> Result<Vec<String>, String> {
+    let contents = if path == "-" {
+        let mut contents = Vec::new();
+        io::stdin().read_to_end(&mut contents).map(|_| contents)
+    } else {
+        fs::read(path)
+    }
+    .map_err(|e| format!("{}: {}", path, e))?;
+    let separator = if contents.contains(&0) { b'\0' } else { b'\n' };
+    Ok(contents
+        .split(|&b| b == separator)
+        .filter(|name| !name.is_empty())
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect())
+}
+
+/// Parses the numeric argument of options like `-A NUM`.
+fn parse_num(option: &str, value: Option<&str>) -> Result<usize, String> {
+    match value {
+        Some(v) => v
+            .parse::<usize>()
+            .map_err(|_| format!("invalid argument '{}' for -{}", v, option)),
+        None => Err(format!("option requires an argument -- '{}'", option)),
+    }
+}
 
 fn parse_args(args: &[String]) -> Result<core::Config, String> {
     if args.len() < 2 {
-        return Err(format!(
-            "Usage: {} [--color[=WHEN]] [-r] [-o [-P]] -E <pattern> [file...]",
-            args[0]
-        ));
+        return Err(usage(&args[0]));
     }
 
     let mut only_matching = false;
-    let mut multi_line = false;
+    let mut syntax = None;
     let mut color_mode = "never".to_string();
+    let mut color_specs = Vec::new();
     let mut recursive = false;
     let mut debug = false;
-    let mut pattern = String::new();
-    let mut filenames = Vec::new();
+    let mut after_context = None;
+    let mut before_context = None;
+    let mut context = 0;
+    let mut group_separator = Some("--".to_string());
+    let mut output_mode = core::OutputMode::Lines;
+    let mut count_matches = false;
+    let mut max_count = None;
+    let mut max_total = None;
+    let mut mmap = None;
+    let mut threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut sort_by_path = false;
+    let mut no_ignore = false;
+    let mut hidden = false;
+    let mut file_globs = Vec::new();
+    let mut exclude_dirs = Vec::new();
+    let mut types = filetypes::TypeRegistry::new();
+    let mut selected_types = Vec::new();
+    let mut negated_types = Vec::new();
+    let mut list_types = false;
+    let mut binary_files = core::BinaryFiles::Binary;
+    let mut no_messages = false;
+    let mut follow = false;
+    let mut max_depth = None;
+    let mut one_file_system = false;
+    let mut json = false;
+    let mut multiline = false;
+    let mut null_data = false;
+    let mut null = false;
+    let mut crlf = core::Crlf::Never;
+    let mut encoding = None;
+    let mut byte_offset = false;
+    let mut files_from = None;
+    let mut replace = None;
+    let mut format = None;
+    let mut in_place = false;
+    let mut dry_run = false;
+    let mut backup = None;
+    let mut pattern = None;
+    let mut positional = Vec::new();
 
     let mut i = 1;
     while i < args.len() {
@@ -40,95 +157,680 @@ fn parse_args(args: &[String]) -> Result<core::Config, String> {
                 color_mode = "never".to_string();
                 i += 1;
             }
+            "--colors" => {
+                let Some(value) = args.get(i + 1) else {
+                    return Err("option '--colors' requires an argument".to_string());
+                };
+                color_specs.push(value.clone());
+                i += 2;
+            }
+            arg if arg.starts_with("--colors=") => {
+                color_specs.push(arg["--colors=".len()..].to_string());
+                i += 1;
+            }
+            "--replace" => {
+                let Some(value) = args.get(i + 1) else {
+                    return Err("option '--replace' requires an argument".to_string());
+                };
+                replace = Some(value.clone());
+                i += 2;
+            }
+            arg if arg.starts_with("--replace=") => {
+                replace = Some(arg["--replace=".len()..].to_string());
+                i += 1;
+            }
+            "--format" => {
+                let Some(value) = args.get(i + 1) else {
+                    return Err("option '--format' requires an argument".to_string());
+                };
+                format = Some(value.clone());
+                i += 2;
+            }
+            arg if arg.starts_with("--format=") => {
+                format = Some(arg["--format=".len()..].to_string());
+                i += 1;
+            }
+            "--in-place" => {
+                in_place = true;
+                i += 1;
+            }
+            "--dry-run" => {
+                dry_run = true;
+                i += 1;
+            }
+            "--backup" => {
+                backup = Some("~".to_string());
+                i += 1;
+            }
+            arg if arg.starts_with("--backup=") => {
+                backup = Some(arg["--backup=".len()..].to_string());
+                i += 1;
+            }
+            "--json" => {
+                json = true;
+                i += 1;
+            }
             "--debug" => {
                 debug = true;
                 i += 1;
             }
-            "-r" => {
+            "-r" | "--recursive" => {
                 recursive = true;
                 i += 1;
             }
-            "-o" => {
-                only_matching = true;
+            "-R" | "--dereference-recursive" => {
+                recursive = true;
+                follow = true;
                 i += 1;
             }
-            "-P" => {
-                if only_matching {
-                    multi_line = true;
-                }
+            "--follow" => {
+                follow = true;
+                i += 1;
+            }
+            "--one-file-system" => {
+                one_file_system = true;
+                i += 1;
+            }
+            "--max-depth" => {
+                max_depth = Some(parse_num("max-depth", args.get(i + 1).map(|s| s.as_str()))?);
+                i += 2;
+            }
+            arg if arg.starts_with("--max-depth=") => {
+                max_depth = Some(parse_num("max-depth", Some(&arg["--max-depth=".len()..]))?);
+                i += 1;
+            }
+            "--no-ignore" => {
+                no_ignore = true;
+                i += 1;
+            }
+            "--hidden" => {
+                hidden = true;
                 i += 1;
             }
-            "-E" => {
-                if i + 1 >= args.len() {
-                    return Err(format!(
-                        "Usage: {} [--color[=WHEN]] [-r] [-o [-P]] -E <pattern> [file...]",
-                        args[0]
-                    ));
+            "--include" | "--exclude" | "--exclude-dir" => {
+                let Some(value) = args.get(i + 1) else {
+                    return Err(format!("option '{}' requires an argument", args[i]));
+                };
+                match args[i].as_str() {
+                    "--include" => file_globs.push((glob::Glob::new(value), true)),
+                    "--exclude" => file_globs.push((glob::Glob::new(value), false)),
+                    _ => exclude_dirs.push(glob::Glob::new(value)),
                 }
-                pattern = args[i + 1].clone();
                 i += 2;
-                // Collect remaining args as filenames
-                while i < args.len() {
-                    filenames.push(args[i].clone());
-                    i += 1;
+            }
+            arg if arg.starts_with("--include=") => {
+                file_globs.push((glob::Glob::new(&arg["--include=".len()..]), true));
+                i += 1;
+            }
+            arg if arg.starts_with("--exclude=") => {
+                file_globs.push((glob::Glob::new(&arg["--exclude=".len()..]), false));
+                i += 1;
+            }
+            arg if arg.starts_with("--exclude-dir=") => {
+                exclude_dirs.push(glob::Glob::new(&arg["--exclude-dir=".len()..]));
+                i += 1;
+            }
+            arg if arg.starts_with("-t") || arg.starts_with("-T") => {
+                let (value, consumed) = short_value(args, i);
+                let Some(value) = value else {
+                    return Err(format!("option requires an argument -- '{}'", &arg[1..2]));
+                };
+                if arg.starts_with("-t") {
+                    selected_types.push(value.to_string());
+                } else {
+                    negated_types.push(value.to_string());
                 }
+                i += consumed;
+            }
+            "--type" | "--type-not" | "--type-add" => {
+                let Some(value) = args.get(i + 1) else {
+                    return Err(format!("option '{}' requires an argument", args[i]));
+                };
+                match args[i].as_str() {
+                    "--type" => selected_types.push(value.clone()),
+                    "--type-not" => negated_types.push(value.clone()),
+                    _ => types.add(value)?,
+                }
+                i += 2;
+            }
+            arg if arg.starts_with("--type=") => {
+                selected_types.push(arg["--type=".len()..].to_string());
+                i += 1;
+            }
+            arg if arg.starts_with("--type-not=") => {
+                negated_types.push(arg["--type-not=".len()..].to_string());
+                i += 1;
+            }
+            arg if arg.starts_with("--type-add=") => {
+                types.add(&arg["--type-add=".len()..])?;
+                i += 1;
+            }
+            "--type-list" => {
+                list_types = true;
+                i += 1;
+            }
+            "-a" | "--text" => {
+                binary_files = core::BinaryFiles::Text;
+                i += 1;
+            }
+            "-I" => {
+                binary_files = core::BinaryFiles::WithoutMatch;
+                i += 1;
+            }
+            "--binary-files"
+            | "--binary-files=binary"
+            | "--binary-files=text"
+            | "--binary-files=without-match" => {
+                let value = match args[i].strip_prefix("--binary-files=") {
+                    Some(value) => value,
+                    None => {
+                        i += 1;
+                        args.get(i).map_or("", |s| s.as_str())
+                    }
+                };
+                binary_files = match value {
+                    "binary" => core::BinaryFiles::Binary,
+                    "text" => core::BinaryFiles::Text,
+                    "without-match" => core::BinaryFiles::WithoutMatch,
+                    _ => return Err(format!("invalid argument '{}' for --binary-files", value)),
+                };
+                i += 1;
+            }
+            "-s" | "--no-messages" => {
+                no_messages = true;
+                i += 1;
+            }
+            "-c" | "--count" => {
+                output_mode = core::OutputMode::Count;
+                i += 1;
+            }
+            "--count-matches" => {
+                output_mode = core::OutputMode::Count;
+                count_matches = true;
+                i += 1;
+            }
+            "-l" | "--files-with-matches" => {
+                output_mode = core::OutputMode::FilesWithMatches;
+                i += 1;
+            }
+            "-L" | "--files-without-match" => {
+                output_mode = core::OutputMode::FilesWithoutMatch;
+                i += 1;
+            }
+            "-q" | "--quiet" | "--silent" => {
+                output_mode = core::OutputMode::Quiet;
+                i += 1;
+            }
+            "--mmap" => {
+                mmap = Some(true);
+                i += 1;
+            }
+            "--no-mmap" => {
+                mmap = Some(false);
+                i += 1;
+            }
+            "--threads" => {
+                threads = parse_num("threads", args.get(i + 1).map(|s| s.as_str()))?;
+                i += 2;
+            }
+            arg if arg.starts_with("--threads=") => {
+                threads = parse_num("threads", Some(&arg["--threads=".len()..]))?;
+                i += 1;
+            }
+            arg if arg.starts_with("-j") => {
+                let (value, consumed) = short_value(args, i);
+                threads = parse_num("j", value)?;
+                i += consumed;
+            }
+            "--sort" | "--sort=path" | "--sort=none" => {
+                let value = match args[i].strip_prefix("--sort=") {
+                    Some(value) => value,
+                    None => {
+                        i += 1;
+                        args.get(i).map_or("", |s| s.as_str())
+                    }
+                };
+                sort_by_path = match value {
+                    "path" => true,
+                    "none" => false,
+                    _ => return Err(format!("invalid argument '{}' for --sort", value)),
+                };
+                i += 1;
+            }
+            "-o" => {
+                only_matching = true;
+                i += 1;
+            }
+            "-U" | "--multiline" => {
+                multiline = true;
+                i += 1;
+            }
+            "-z" | "--null-data" => {
+                null_data = true;
+                i += 1;
+            }
+            "-Z" | "--null" => {
+                null = true;
+                i += 1;
+            }
+            "--crlf" => {
+                crlf = core::Crlf::Always;
+                i += 1;
+            }
+            arg if arg.starts_with("--crlf=") => {
+                crlf = match &arg["--crlf=".len()..] {
+                    "always" => core::Crlf::Always,
+                    "auto" => core::Crlf::Auto,
+                    "never" => core::Crlf::Never,
+                    value => return Err(format!("invalid argument '{}' for --crlf", value)),
+                };
+                i += 1;
+            }
+            "--encoding" => {
+                let Some(value) = args.get(i + 1) else {
+                    return Err("option '--encoding' requires an argument".to_string());
+                };
+                encoding = Some(parse_encoding(value)?);
+                i += 2;
+            }
+            arg if arg.starts_with("--encoding=") => {
+                encoding = Some(parse_encoding(&arg["--encoding=".len()..])?);
+                i += 1;
+            }
+            "-b" | "--byte-offset" => {
+                byte_offset = true;
+                i += 1;
+            }
+            "--files-from" => {
+                let Some(value) = args.get(i + 1) else {
+                    return Err("option '--files-from' requires an argument".to_string());
+                };
+                files_from = Some(value.clone());
+                i += 2;
+            }
+            arg if arg.starts_with("--files-from=") => {
+                files_from = Some(arg["--files-from=".len()..].to_string());
+                i += 1;
+            }
+            "-G" | "--basic-regexp" => {
+                select_syntax(&mut syntax, Syntax::Basic)?;
+                i += 1;
+            }
+            "-E" | "--extended-regexp" => {
+                select_syntax(&mut syntax, Syntax::Extended)?;
+                i += 1;
+            }
+            "-P" | "--perl-regexp" => {
+                select_syntax(&mut syntax, Syntax::Perl)?;
+                i += 1;
+            }
+            "-e" | "--regexp" => {
+                let Some(value) = args.get(i + 1) else {
+                    return Err(format!("option '{}' requires an argument", args[i]));
+                };
+                pattern = Some(value.clone());
+                i += 2;
+            }
+            arg if arg.starts_with("--regexp=") => {
+                pattern = Some(arg["--regexp=".len()..].to_string());
+                i += 1;
+            }
+            arg if arg.starts_with("-m") => {
+                let (value, consumed) = short_value(args, i);
+                max_count = Some(parse_num("m", value)?);
+                i += consumed;
+            }
+            "--max-count" => {
+                max_count = Some(parse_num("m", args.get(i + 1).map(|s| s.as_str()))?);
+                i += 2;
+            }
+            arg if arg.starts_with("--max-count=") => {
+                max_count = Some(parse_num("m", Some(&arg["--max-count=".len()..]))?);
+                i += 1;
+            }
+            "--max-total" => {
+                max_total = Some(parse_num("max-total", args.get(i + 1).map(|s| s.as_str()))?);
+                i += 2;
+            }
+            arg if arg.starts_with("--max-total=") => {
+                max_total = Some(parse_num("max-total", Some(&arg["--max-total=".len()..]))?);
+                i += 1;
+            }
+            arg if arg.starts_with("-A") => {
+                let (value, consumed) = short_value(args, i);
+                after_context = Some(parse_num("A", value)?);
+                i += consumed;
+            }
+            arg if arg.starts_with("-B") => {
+                let (value, consumed) = short_value(args, i);
+                before_context = Some(parse_num("B", value)?);
+                i += consumed;
+            }
+            arg if arg.starts_with("-C") => {
+                let (value, consumed) = short_value(args, i);
+                context = parse_num("C", value)?;
+                i += consumed;
+            }
+            "--no-group-separator" => {
+                group_separator = None;
+                i += 1;
+            }
+            "--after-context" => {
+                after_context = Some(parse_num("A", args.get(i + 1).map(|s| s.as_str()))?);
+                i += 2;
+            }
+            "--before-context" => {
+                before_context = Some(parse_num("B", args.get(i + 1).map(|s| s.as_str()))?);
+                i += 2;
+            }
+            "--context" => {
+                context = parse_num("C", args.get(i + 1).map(|s| s.as_str()))?;
+                i += 2;
+            }
+            arg if arg.starts_with("--after-context=") => {
+                after_context = Some(parse_num("A", Some(&arg["--after-context=".len()..]))?);
+                i += 1;
+            }
+            arg if arg.starts_with("--before-context=") => {
+                before_context = Some(parse_num("B", Some(&arg["--before-context=".len()..]))?);
+                i += 1;
+            }
+            arg if arg.starts_with("--context=") => {
+                context = parse_num("C", Some(&arg["--context=".len()..]))?;
+                i += 1;
+            }
+            "--group-separator" => {
+                let Some(value) = args.get(i + 1) else {
+                    return Err("option '--group-separator' requires an argument".to_string());
+                };
+                group_separator = Some(value.clone());
+                i += 2;
+            }
+            arg if arg.starts_with("--group-separator=") => {
+                group_separator = Some(arg["--group-separator=".len()..].to_string());
+                i += 1;
+            }
+            "--" => {
+                positional.extend_from_slice(&args[i + 1..]);
                 break;
             }
+            arg if arg == "-" || !arg.starts_with('-') => {
+                positional.push(args[i].clone());
+                i += 1;
+            }
             _ => {
-                return Err(format!(
-                    "Usage: {} [--color[=WHEN]] [-r] [-o [-P]] -E <pattern> [file...]",
-                    args[0]
-                ));
+                return Err(usage(&args[0]));
             }
         }
     }
 
-    if pattern.is_empty() {
-        return Err(format!(
-            "Usage: {} [--color[=WHEN]] [-r] [-o [-P]] -E <pattern> [file...]",
-            args[0]
-        ));
+    if list_types {
+        for line in types.list() {
+            println!("{}", line);
+        }
+        process::exit(0);
+    }
+
+    // The pattern is the first operand unless -e gave it; the rest are files
+    let mut filenames = positional.into_iter();
+    let Some(pattern) = pattern.or_else(|| filenames.next()) else {
+        return Err(usage(&args[0]));
+    };
+    let mut filenames: Vec<String> = filenames.collect();
+    if let Some(list) = files_from {
+        let listed = read_file_list(&list)?;
+        // An empty list means nothing to search, not standard input
+        if listed.is_empty() && filenames.is_empty() {
+            process::exit(1);
+        }
+        filenames.extend(listed);
+    }
+    // As in GNU grep, a recursive search without files searches the working
+    // directory rather than standard input
+    let implicit_root = recursive && filenames.is_empty();
+    if implicit_root {
+        filenames.push(".".to_string());
+    }
+    if json
+        && !matches!(
+            output_mode,
+            core::OutputMode::Lines | core::OutputMode::Quiet
+        )
+    {
+        return Err("--json can't be combined with -c, -l or -L".to_string());
+    }
+    if json && (replace.is_some() || only_matching) {
+        return Err("--json can't be combined with --replace or -o".to_string());
+    }
+    if in_place || dry_run {
+        if replace.is_none() {
+            return Err("--in-place and --dry-run require --replace".to_string());
+        }
+        if filenames.is_empty() {
+            return Err("--in-place and --dry-run need files to rewrite".to_string());
+        }
+        if json || output_mode != core::OutputMode::Lines {
+            return Err("--in-place can't be combined with --json, -c, -l, -L or -q".to_string());
+        }
+    } else if backup.is_some() {
+        return Err("--backup requires --in-place".to_string());
+    }
+    if (in_place || dry_run) && encoding.is_some_and(|e| e != encoding::Encoding::Utf8) {
+        return Err("--in-place can only rewrite UTF-8 files".to_string());
+    }
+    if multiline && (json || in_place || dry_run) {
+        return Err("-U can't be combined with --json or --in-place".to_string());
+    }
+    if format.is_some() {
+        if json || replace.is_some() {
+            return Err("--format can't be combined with --json or --replace".to_string());
+        }
+        if output_mode != core::OutputMode::Lines && output_mode != core::OutputMode::Quiet {
+            return Err("--format can't be combined with -c, -l or -L".to_string());
+        }
     }
+    // NO_COLOR (https://no-color.org) only turns off automatic color
+    let colored = match color_mode.as_str() {
+        "always" => true,
+        "auto" => {
+            atty::is(atty::Stream::Stdout) && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        }
+        _ => false,
+    };
+    let colors = if colored {
+        let mut colors = color::Colors::default();
+        // Like GNU grep, stop at a malformed GREP_COLORS rather than refuse to run
+        if let Ok(spec) = env::var("GREP_COLORS") {
+            let _ = colors.apply(&spec);
+        }
+        for spec in &color_specs {
+            colors.apply(spec)?;
+        }
+        Some(colors)
+    } else {
+        for spec in &color_specs {
+            color::Colors::default().apply(spec)?;
+        }
+        None
+    };
+    let file_types = types.filter(&selected_types, &negated_types)?;
+    let regex = parser::regex::Regex::new(
+        &pattern,
+        syntax.unwrap_or(Syntax::Basic),
+        crlf != core::Crlf::Never,
+    );
+    let format = match format {
+        Some(format) => Some(format::Format::new(&format, &regex)?),
+        None => None,
+    };
+    let replace = match replace {
+        Some(template) => Some(replace::Template::new(&template, &regex)?),
+        None => None,
+    };
 
     Ok(core::Config {
         pattern,
         filenames,
-        color_mode,
+        implicit_root,
+        colors,
         recursive,
         only_matching,
-        multi_line,
+        regex,
         debug,
+        // Explicit -A/-B win over -C regardless of order, as in GNU grep
+        after_context: after_context.unwrap_or(context),
+        before_context: before_context.unwrap_or(context),
+        group_separator,
+        output_mode,
+        count_matches,
+        max_count,
+        max_total,
+        mmap,
+        threads,
+        sort_by_path,
+        no_ignore,
+        hidden,
+        file_globs,
+        exclude_dirs,
+        file_types,
+        binary_files,
+        no_messages,
+        follow,
+        max_depth,
+        one_file_system,
+        json,
+        multiline,
+        null_data,
+        null,
+        crlf,
+        encoding,
+        byte_offset,
+        replace,
+        in_place: in_place || dry_run,
+        dry_run,
+        backup,
+        format,
     })
 }
 
+/// The per-file match limit: whichever of `-m` and what is left of
+/// `--max-total` is smaller.
+fn match_limit(config: &core::Config, total_left: Option<usize>) -> Option<usize> {
+    match (config.max_count, total_left) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
 fn process_stdin(config: &core::Config) -> Result<bool, Box<dyn std::error::Error>> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
-    Ok(core::search_file(&input, config, None))
+    let limit = match_limit(config, config.max_total);
+    let mut out = io::stdout().lock();
+    let reader =
+        match reader::LineReader::decoded(io::stdin(), config.line_terminator(), config.encoding) {
+            Ok(reader) => reader,
+            Err(e) => {
+                core::report_error(config, "(standard input)", &e);
+                return Ok(false);
+            }
+        };
+    let matches = match core::search_file(reader, config, None, limit, &mut false, &mut out) {
+        Ok(matches) => matches,
+        Err(core::SearchError::Input(e)) => {
+            core::report_error(config, "(standard input)", &e);
+            return Ok(false);
+        }
+        Err(core::SearchError::Output(e)) => return Err(e.into()),
+    };
+    Ok(core::report_file(
+        &mut out,
+        config,
+        Path::new("(standard input)"),
+        false,
+        &matches,
+    )?)
 }
 
 fn process_files(config: &core::Config) -> Result<bool, Box<dyn std::error::Error>> {
-    let file_paths = core::collect_files_recursive(&config.filenames, config.recursive);
-    let should_prefix = file_paths.len() > 1 || config.recursive;
+    // JSON events always carry the path, and --format has it as {path}
+    let should_prefix =
+        config.filenames.len() > 1 || config.recursive || config.json || config.format.is_some();
+    // A single file is searched on this thread so its output streams as it's found
+    if config.threads > 1 && should_prefix && config.max_total.is_none() {
+        return parallel::process_files(config, should_prefix);
+    }
+
     let mut matched_any = false;
+    let mut total_left = config.max_total;
+    let mut error: Option<io::Error> = None;
+    let mut grouped = false;
+    let mut out = io::stdout().lock();
 
-    for filename in &file_paths {
-        let content = std::fs::read_to_string(filename)?;
-        let filename_opt = if should_prefix {
-            Some(filename.as_str())
-        } else {
-            None
+    let mut search_one = |filename: &Path| {
+        let filename_opt = if should_prefix { Some(filename) } else { None };
+        let limit = match_limit(config, total_left);
+
+        let searched = core::search_path(
+            filename,
+            config,
+            filename_opt,
+            limit,
+            &mut grouped,
+            &mut out,
+        );
+        let matches = match searched {
+            Ok(matches) => matches,
+            Err(core::SearchError::Input(e)) => {
+                core::report_error(config, &filename.to_string_lossy(), &e);
+                return true;
+            }
+            Err(core::SearchError::Output(e)) => {
+                error = Some(e);
+                return false;
+            }
         };
+        match core::report_file(&mut out, config, filename, should_prefix, &matches) {
+            Ok(matched) => matched_any |= matched,
+            Err(e) => {
+                error = Some(e);
+                return false;
+            }
+        }
+        let count = matches.count;
 
-        if core::search_file(&content, config, filename_opt) {
-            matched_any = true;
+        if let Some(left) = total_left.as_mut() {
+            *left = left.saturating_sub(count);
+            if *left == 0 {
+                return false;
+            }
+        }
+        // -q only needs one match, so stop walking the tree as soon as we have it
+        !(matched_any && config.output_mode == core::OutputMode::Quiet)
+    };
+
+    if config.sort_by_path {
+        let mut paths = Vec::new();
+        core::collect_files_recursive(config, &mut |path| {
+            paths.push(path.to_path_buf());
+            true
+        });
+        paths.sort();
+        for path in &paths {
+            if !search_one(path) {
+                break;
+            }
         }
+    } else {
+        core::collect_files_recursive(config, &mut search_one);
     }
 
-    Ok(matched_any)
+    match error {
+        Some(e) => Err(e.into()),
+        None => Ok(matched_any),
+    }
 }
 
 fn main() {
+    let start = Instant::now();
     let args: Vec<String> = env::args().collect();
 
     let config = match parse_args(&args) {
@@ -139,17 +841,32 @@ fn main() {
         }
     };
 
-    let matched_any = if config.filenames.is_empty() {
-        process_stdin(&config).unwrap_or(false)
-    } else {
-        process_files(&config).unwrap_or_else(|e| {
-            eprintln!("{}", e);
-            process::exit(1);
-        })
+    let result = match &config.replace {
+        Some(template) if config.in_place => rewrite::process_files(&config, template),
+        _ if config.filenames.is_empty() => process_stdin(&config),
+        _ => process_files(&config),
     };
+    let matched_any = result.unwrap_or_else(|e| {
+        // A closed stdout (`rgrep ... | head`) isn't worth a message
+        let broken_pipe = e
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe);
+        if !broken_pipe {
+            eprintln!("rgrep: {}", e);
+        }
+        process::exit(2);
+    });
+    if config.json && config.output_mode != core::OutputMode::Quiet {
+        // Nothing useful to do if stdout is already gone
+        let _ = json::print_summary(&mut io::stdout().lock(), start.elapsed());
+    }
 
-    if matched_any {
+    // As in GNU grep, an error makes the status 2 even if something matched,
+    // unless -q got the one match it asked for
+    if matched_any && (!core::had_errors() || config.output_mode == core::OutputMode::Quiet) {
         process::exit(0);
+    } else if core::had_errors() {
+        process::exit(2);
     } else {
         process::exit(1);
     }
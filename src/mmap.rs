@@ -0,0 +1,91 @@
+use std::fs::File;
+use std::io;
+
+/// Files at least this large are memory-mapped unless `--no-mmap` is given.
+/// Below it, setting up the mapping costs more than it saves over `read`.
+pub const MMAP_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Decides whether `file` should be searched through a memory map. `choice` is
+/// `Some(true)` for `--mmap`, `Some(false)` for `--no-mmap` and `None` to use the
+/// size heuristic. Pipes, sockets and other special files are never mapped.
+pub fn should_mmap(file: &File, choice: Option<bool>) -> bool {
+    if choice == Some(false) || !cfg!(unix) {
+        return false;
+    }
+    match file.metadata() {
+        Ok(meta) if meta.is_file() && meta.len() > 0 => {
+            choice == Some(true) || meta.len() >= MMAP_THRESHOLD
+        }
+        _ => false,
+    }
+}
+
+/// A read-only, private memory map of a whole file.
+///
+/// The mapped bytes are only valid as long as nobody truncates the file under
+/// us, which is the usual caveat for grep-like tools using mmap.
+pub struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    #[cfg(unix)]
+    pub fn map(file: &File) -> io::Result<Mmap> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large to map"))?;
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot map an empty file",
+            ));
+        }
+
+        // SAFETY: we map a file descriptor we own read-only and privately, and
+        // check the result before using the pointer.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Purely a hint, so a failure doesn't matter
+        unsafe {
+            libc::madvise(ptr, len, libc::MADV_SEQUENTIAL);
+        }
+
+        Ok(Mmap { ptr, len })
+    }
+
+    #[cfg(not(unix))]
+    pub fn map(_file: &File) -> io::Result<Mmap> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "memory maps are only supported on unix",
+        ))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        // SAFETY: `ptr` points to `len` readable bytes until we unmap in `drop`
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::color::Colors;
+use crate::core::{self, Captures, Config, ContextWindow, FileMatches, OutputMode, SearchError};
+use crate::encoding::Decoding;
+use crate::format::FormatMatch;
+use crate::parser::input::DecodedLine;
+use crate::reader::LineSource;
+
+/// The whole input of a `-U` search: its lines joined by their terminator, so a
+/// pattern can match across them.
+struct Buffer<'a> {
+    text: DecodedLine<'a>,
+    /// Char index where each line starts
+    line_starts: Vec<usize>,
+    /// Byte offset in the original input where each line starts
+    line_offsets: Vec<usize>,
+    decoding: Decoding,
+}
+
+impl Buffer<'_> {
+    fn line_of(&self, index: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= index) - 1
+    }
+
+    /// Char range of line `line`, without its terminator.
+    fn line_range(&self, line: usize) -> (usize, usize) {
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map_or(self.text.chars.len(), |next| next - 1);
+        (self.line_starts[line], end)
+    }
+
+    fn line_bytes(&self, line: usize) -> &[u8] {
+        let (start, end) = self.line_range(line);
+        self.text.slice(start, end)
+    }
+
+    /// Byte offset in the original input of the char at `index`.
+    fn offset_of(&self, index: usize) -> usize {
+        let line = self.line_of(index);
+        let start = self.line_starts[line];
+        self.line_offsets[line] + self.decoding.original_len(self.text.slice(start, index))
+    }
+
+    /// The first and last line a match touches; an empty one touches the line
+    /// it's on.
+    fn lines_of(&self, (start, end): (usize, usize)) -> (usize, usize) {
+        (self.line_of(start), self.line_of(end.max(start + 1) - 1))
+    }
+}
+
+/// Matches whose lines overlap, printed together.
+struct Block {
+    first: usize,
+    last: usize,
+    matches: Vec<Captures>,
+}
+
+/// Line `line` with the parts of `matches` on it colored, as
+/// `core::highlight_matches_in_line` does for a single line.
+fn highlight_line(buffer: &Buffer, line: usize, matches: &[Captures], colors: &Colors) -> Vec<u8> {
+    let (line_start, line_end) = buffer.line_range(line);
+    let mut output = Vec::new();
+    let mut pos = line_start;
+    for ((start, end), groups) in matches {
+        let (start, end) = ((*start).max(line_start), (*end).min(line_end));
+        if start >= end {
+            continue;
+        }
+        Colors::paint(
+            &mut output,
+            &colors.selected_line,
+            buffer.text.slice(pos, start),
+        );
+        core::push_match(
+            &mut output,
+            &buffer.text,
+            (start, end),
+            groups,
+            Some(colors),
+        );
+        pos = end;
+    }
+    Colors::paint(
+        &mut output,
+        &colors.selected_line,
+        buffer.text.slice(pos, line_end),
+    );
+    output
+}
+
+/// Prints a block: every line it touches, or with `--replace` its lines with
+/// the matches replaced.
+fn print_block(
+    out: &mut dyn Write,
+    buffer: &Buffer,
+    block: &Block,
+    config: &Config,
+    filename: Option<&Path>,
+) -> io::Result<()> {
+    let colors = config.colors.as_ref();
+    if let Some(template) = &config.replace {
+        let (start, _) = buffer.line_range(block.first);
+        let (_, end) = buffer.line_range(block.last);
+        let mut output = Vec::new();
+        let mut pos = start;
+        for ((match_start, match_end), groups) in &block.matches {
+            output.extend_from_slice(buffer.text.slice(pos, *match_start));
+            let replaced = template.expand(
+                buffer.text.slice(*match_start, *match_end),
+                &core::group_texts(&buffer.text, groups),
+            );
+            match colors {
+                Some(colors) => Colors::paint(&mut output, &colors.selected_match, &replaced),
+                None => output.extend_from_slice(&replaced),
+            }
+            pos = *match_end;
+        }
+        output.extend_from_slice(buffer.text.slice(pos, end));
+        let offset = buffer.line_offsets[block.first];
+        return core::print_line(out, config, filename, ':', offset, &output);
+    }
+    for line in block.first..=block.last {
+        let offset = buffer.line_offsets[line];
+        match colors {
+            Some(scheme) => {
+                let output = highlight_line(buffer, line, &block.matches, scheme);
+                core::print_line(out, config, filename, ':', offset, &output)?
+            }
+            None => core::print_line(out, config, filename, ':', offset, buffer.line_bytes(line))?,
+        }
+    }
+    Ok(())
+}
+
+/// Prints each non-empty match itself, for `-o` and `--format`.
+fn print_matches(
+    out: &mut dyn Write,
+    buffer: &Buffer,
+    matches: &[Captures],
+    config: &Config,
+    filename: Option<&Path>,
+) -> io::Result<()> {
+    let colors = config.colors.as_ref();
+    let path = filename.map_or(b"(standard input)".as_slice(), core::path_bytes);
+    for ((start, end), groups) in matches.iter().filter(|((start, end), _)| start < end) {
+        let captures = core::group_texts(&buffer.text, groups);
+        let offset = buffer.offset_of(*start);
+        if let Some(format) = &config.format {
+            let line = buffer.line_of(*start);
+            let found = FormatMatch {
+                path,
+                line_number: line + 1,
+                column: offset - buffer.line_offsets[line] + 1,
+                offset,
+                text: buffer.text.slice(*start, *end),
+                captures: &captures,
+            };
+            format.write(out, &found, colors)?;
+            continue;
+        }
+        let mut output = Vec::new();
+        match &config.replace {
+            Some(template) => {
+                let replaced = template.expand(buffer.text.slice(*start, *end), &captures);
+                match colors {
+                    Some(colors) => Colors::paint(&mut output, &colors.selected_match, &replaced),
+                    None => output.extend_from_slice(&replaced),
+                }
+            }
+            None => core::push_match(&mut output, &buffer.text, (*start, *end), groups, colors),
+        }
+        core::print_line(out, config, filename, ':', offset, &output)?;
+    }
+    Ok(())
+}
+
+/// `-U`: searches the whole of `reader` at once, so matches can span lines, and
+/// prints every line a match touches. Counts, `-m` and context all go by those
+/// lines, as they go by matching lines without `-U`.
+pub fn search_file<S: LineSource>(
+    mut reader: S,
+    config: &Config,
+    filename: Option<&Path>,
+    limit: Option<usize>,
+    binary: bool,
+    grouped: &mut bool,
+    out: &mut dyn Write,
+) -> Result<FileMatches, SearchError> {
+    let crlf = config
+        .crlf
+        .applies(reader.first_block().map_err(SearchError::Input)?);
+    let decoding = reader.decoding();
+    let terminator_len = decoding.original_len(&[config.line_terminator()]);
+    let mut contents = Vec::new();
+    let mut line_offsets = Vec::new();
+    let mut offset = decoding.bom_len;
+    while let Some(bytes) = reader.next_line().map_err(SearchError::Input)? {
+        if !line_offsets.is_empty() {
+            contents.push(config.line_terminator());
+        }
+        line_offsets.push(offset);
+        offset += decoding.original_len(bytes) + terminator_len;
+        contents.extend_from_slice(bytes);
+    }
+    if line_offsets.is_empty() || limit == Some(0) {
+        return Ok(FileMatches { count: 0, binary });
+    }
+    let text = DecodedLine::new(&contents).with_crlf(crlf);
+    let mut line_starts = vec![0];
+    line_starts.extend(
+        text.chars
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| **c == config.line_terminator() as char)
+            .map(|(i, _)| i + 1),
+    );
+    let buffer = Buffer {
+        text,
+        line_starts,
+        line_offsets,
+        decoding,
+    };
+
+    // Group the matches into blocks, up to the -m limit of lines
+    let only_first_match = config.output_mode.stops_at_first_match()
+        || binary && config.output_mode == OutputMode::Lines;
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut matched_lines = 0;
+    let mut match_count = 0;
+    for found in config.regex.find_iter(buffer.text.text()) {
+        if limit.is_some_and(|limit| matched_lines >= limit) {
+            break;
+        }
+        let (first, last) = buffer.lines_of(found.0);
+        if found.0.0 < found.0.1 {
+            match_count += 1;
+        }
+        match blocks.last_mut() {
+            Some(block) if first <= block.last => {
+                matched_lines += last.saturating_sub(block.last);
+                block.last = block.last.max(last);
+                block.matches.push(found);
+            }
+            _ => {
+                matched_lines += last - first + 1;
+                blocks.push(Block {
+                    first,
+                    last,
+                    matches: vec![found],
+                });
+            }
+        }
+        if only_first_match {
+            break;
+        }
+    }
+
+    let count = if config.count_matches {
+        match_count
+    } else {
+        matched_lines
+    };
+    if config.output_mode != OutputMode::Lines || binary {
+        return Ok(FileMatches { count, binary });
+    }
+
+    if config.only_matching || config.format.is_some() {
+        for block in &blocks {
+            print_matches(out, &buffer, &block.matches, config, filename)?;
+        }
+        return Ok(FileMatches { count, binary });
+    }
+
+    let mut window = ContextWindow::new(config, *grouped);
+    let mut line = 0;
+    for block in &blocks {
+        while line < block.first {
+            let offset = buffer.line_offsets[line];
+            window.non_match(out, line, offset, buffer.line_bytes(line), filename)?;
+            line += 1;
+        }
+        window.before_match(out, block.first, filename)?;
+        print_block(out, &buffer, block, config, filename)?;
+        window.after_match(block.last);
+        line = block.last + 1;
+    }
+    while line < buffer.line_starts.len() && window.in_after_context() {
+        let offset = buffer.line_offsets[line];
+        window.non_match(out, line, offset, buffer.line_bytes(line), filename)?;
+        line += 1;
+    }
+    *grouped = window.grouped();
+    Ok(FileMatches { count, binary })
+}
@@ -0,0 +1,261 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, mpsc};
+use std::thread;
+use std::time::Duration;
+
+use crate::core::{self, Config, OutputMode, SearchError, WalkDir, WalkVisitor};
+
+/// How long an idle walker waits before looking for work again.
+const IDLE_WAIT: Duration = Duration::from_micros(200);
+
+/// Directory queues of the parallel walker, one per walker thread.
+///
+/// A walker pushes the subdirectories it finds onto its own queue and takes work
+/// from the back of it, so each thread walks depth first. A walker whose queue
+/// is empty steals from the front of another queue, where the oldest and
+/// usually largest subtrees are.
+struct WalkQueues {
+    queues: Vec<Mutex<VecDeque<WalkDir>>>,
+    /// Directories queued or being read; the walk is over once this drops to 0
+    pending: AtomicUsize,
+}
+
+impl WalkQueues {
+    fn new(workers: usize) -> Self {
+        WalkQueues {
+            queues: (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&self, worker: usize, dir: WalkDir) {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.queues[worker].lock().unwrap().push_back(dir);
+    }
+
+    fn pop(&self, worker: usize) -> Option<WalkDir> {
+        if let Some(dir) = self.queues[worker].lock().unwrap().pop_back() {
+            return Some(dir);
+        }
+        let workers = self.queues.len();
+        (1..workers).find_map(|offset| {
+            self.queues[(worker + offset) % workers]
+                .lock()
+                .unwrap()
+                .pop_front()
+        })
+    }
+}
+
+/// Visitor of a walker thread: subdirectories go onto the thread's own queue,
+/// files to the search workers.
+struct ParallelWalk<'a> {
+    worker: usize,
+    config: &'a Config,
+    queues: &'a WalkQueues,
+    files: mpsc::Sender<PathBuf>,
+    stop: &'a AtomicBool,
+}
+
+impl WalkVisitor for ParallelWalk<'_> {
+    fn file(&mut self, path: &Path) -> bool {
+        !self.stop.load(Ordering::Relaxed) && self.files.send(path.to_path_buf()).is_ok()
+    }
+
+    fn dir(&mut self, dir: WalkDir) -> bool {
+        self.queues.push(self.worker, dir);
+        !self.stop.load(Ordering::Relaxed)
+    }
+}
+
+fn walk_worker(mut visitor: ParallelWalk) {
+    while !visitor.stop.load(Ordering::Relaxed) {
+        match visitor.queues.pop(visitor.worker) {
+            Some(dir) => {
+                core::collect_from_dir(&dir, visitor.config, &mut visitor);
+                visitor.queues.pending.fetch_sub(1, Ordering::SeqCst);
+            }
+            None if visitor.queues.pending.load(Ordering::SeqCst) == 0 => break,
+            None => thread::sleep(IDLE_WAIT),
+        }
+    }
+}
+
+/// Output of searching one file, whether it counts towards success, and whether
+/// it printed a context group.
+type FileResult = Result<(Vec<u8>, bool, bool), SearchError>;
+
+/// Searches one file into a buffer, so its lines can be written in one go and
+/// never interleave with those of files searched on other threads. The buffer
+/// doesn't start with a group separator; that's up to whoever writes it out.
+fn search_to_buffer(path: &Path, config: &Config, prefix: bool) -> FileResult {
+    let mut out = Vec::new();
+    let filename = if prefix { Some(path) } else { None };
+    let mut grouped = false;
+    let matches = core::search_path(
+        path,
+        config,
+        filename,
+        config.max_count,
+        &mut grouped,
+        &mut out,
+    )?;
+    let matched = core::report_file(&mut out, config, path, prefix, &matches)?;
+    Ok((out, matched, grouped))
+}
+
+/// Where the search workers take the next file from, along with its position
+/// in the output.
+enum FileQueue {
+    /// Files in the order the walkers find them
+    Walk(Mutex<(mpsc::Receiver<PathBuf>, usize)>),
+    /// Every file, sorted by path
+    Sorted(Vec<PathBuf>, AtomicUsize),
+}
+
+impl FileQueue {
+    fn next(&self) -> Option<(usize, PathBuf)> {
+        match self {
+            FileQueue::Walk(receiver) => {
+                let mut guard = receiver.lock().unwrap();
+                let path = guard.0.recv().ok()?;
+                guard.1 += 1;
+                Some((guard.1 - 1, path))
+            }
+            FileQueue::Sorted(paths, next) => {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                paths.get(index).map(|path| (index, path.clone()))
+            }
+        }
+    }
+}
+
+/// Queues the command line paths and starts the walker threads, which send
+/// every file they find to `files`.
+fn spawn_walkers<'scope, 'env>(
+    scope: &'scope thread::Scope<'scope, 'env>,
+    config: &'env Config,
+    queues: &'env WalkQueues,
+    stop: &'env AtomicBool,
+    files: mpsc::Sender<PathBuf>,
+) {
+    let mut roots = ParallelWalk {
+        worker: 0,
+        config,
+        queues,
+        files: files.clone(),
+        stop,
+    };
+    core::walk_roots(config, &mut roots);
+
+    for worker in 0..queues.queues.len() {
+        let visitor = ParallelWalk {
+            worker,
+            config,
+            queues,
+            files: files.clone(),
+            stop,
+        };
+        scope.spawn(move || walk_worker(visitor));
+    }
+}
+
+/// Starts the search threads on `queue` and writes their output from the
+/// calling thread: as files finish, or in queue order when `in_order` is set.
+fn search_and_print<'scope, 'env>(
+    scope: &'scope thread::Scope<'scope, 'env>,
+    config: &'env Config,
+    prefix: bool,
+    queue: &'env FileQueue,
+    stop: &'env AtomicBool,
+    in_order: bool,
+) -> Result<bool, Box<dyn Error>> {
+    let (result_tx, result_rx) = mpsc::channel::<(usize, PathBuf, FileResult)>();
+    for _ in 0..config.threads.max(1) {
+        let result_tx = result_tx.clone();
+        scope.spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                let Some((index, path)) = queue.next() else {
+                    break;
+                };
+                let result = search_to_buffer(&path, config, prefix);
+                if result_tx.send((index, path, result)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(result_tx);
+
+    let mut stdout = io::stdout().lock();
+    let mut matched_any = false;
+    let mut grouped_any = false;
+    let mut waiting = BTreeMap::new();
+    let mut next_index = 0;
+
+    let outcome = (|| {
+        for (index, path, result) in result_rx.iter() {
+            waiting.insert(index, (path, result));
+            while let Some((path, result)) = if in_order {
+                waiting.remove(&next_index)
+            } else {
+                waiting.pop_first().map(|(_, entry)| entry)
+            } {
+                next_index += 1;
+                let (buffer, matched, grouped) = match result {
+                    Ok(output) => output,
+                    Err(SearchError::Input(e)) => {
+                        core::report_error(config, &path.to_string_lossy(), &e);
+                        continue;
+                    }
+                    Err(SearchError::Output(e)) => return Err(e.into()),
+                };
+                if grouped_any && grouped {
+                    core::print_group_separator(&mut stdout, config)?;
+                }
+                stdout.write_all(&buffer)?;
+                matched_any |= matched;
+                grouped_any |= grouped;
+                // -q only needs one match
+                if matched_any && config.output_mode == OutputMode::Quiet {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(matched_any)
+    })();
+
+    // Winds down the walkers and searchers early on errors and for -q
+    stop.store(true, Ordering::Relaxed);
+    outcome
+}
+
+/// Walks and searches `config.filenames` on `config.threads` walker threads and
+/// as many search threads. Output is written by the calling thread, file by
+/// file, either as files finish or in path order with `--sort path`.
+///
+/// `--max-total` needs a running total across files and isn't supported here;
+/// callers use the serial search for it.
+pub fn process_files(config: &Config, prefix: bool) -> Result<bool, Box<dyn Error>> {
+    let queues = WalkQueues::new(config.threads.max(1));
+    let stop = AtomicBool::new(false);
+    let (file_tx, file_rx) = mpsc::channel::<PathBuf>();
+
+    if config.sort_by_path {
+        thread::scope(|scope| spawn_walkers(scope, config, &queues, &stop, file_tx));
+        let mut paths: Vec<PathBuf> = file_rx.iter().collect();
+        paths.sort();
+        let queue = FileQueue::Sorted(paths, AtomicUsize::new(0));
+        thread::scope(|scope| search_and_print(scope, config, prefix, &queue, &stop, true))
+    } else {
+        let queue = FileQueue::Walk(Mutex::new((file_rx, 0)));
+        thread::scope(|scope| {
+            spawn_walkers(scope, config, &queues, &stop, file_tx);
+            search_and_print(scope, config, prefix, &queue, &stop, false)
+        })
+    }
+}
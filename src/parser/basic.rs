@@ -0,0 +1,70 @@
+use crate::parser::bracket;
+use crate::parser::regex::Syntax;
+
+/// Rewrites a basic regular expression (`-G`) in the extended syntax the
+/// tokenizer reads, so both share one `Token` representation.
+///
+/// In a BRE `\(`, `\)`, `\{`, `\}`, `\|`, `\+` and `\?` are the operators and
+/// the bare characters are literals. `*` is literal at the start of an
+/// expression, `^` is only an anchor at the start and `$` only at the end,
+/// where an expression also starts after `\(` or `\|` and ends before `\)` or
+/// `\|`. Bracket expressions and other escapes are copied as they are.
+pub fn to_extended(pattern: &str) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut out = String::with_capacity(pattern.len());
+    // Whether the next char starts an expression, and whether an anchor
+    // just started one
+    let mut at_start = true;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let was_start = at_start;
+        at_start = false;
+        match c {
+            '\\' if i + 1 < chars.len() => {
+                let next = chars[i + 1];
+                i += 2;
+                match next {
+                    '(' | '|' => {
+                        out.push(next);
+                        at_start = true;
+                    }
+                    ')' | '{' | '}' | '+' | '?' => out.push(next),
+                    _ => {
+                        out.push('\\');
+                        out.push(next);
+                    }
+                }
+                continue;
+            }
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '*' if was_start => out.push_str("\\*"),
+            '^' if was_start => {
+                out.push('^');
+                at_start = true;
+            }
+            '^' => out.push_str("\\^"),
+            '$' if !ends_expression(&chars, i + 1) => out.push_str("\\$"),
+            '[' => match bracket::end(&chars, i, Syntax::Basic) {
+                Some(end) => {
+                    out.extend(&chars[i..end]);
+                    i = end;
+                    continue;
+                }
+                None => out.push_str("\\["),
+            },
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+    out
+}
+
+/// Whether an expression ends at char `i`: at the end of the pattern or
+/// before `\)` or `\|`.
+fn ends_expression(chars: &[char], i: usize) -> bool {
+    i == chars.len() || (chars[i] == '\\' && matches!(chars.get(i + 1), Some(')' | '|')))
+}
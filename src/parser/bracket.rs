@@ -0,0 +1,119 @@
+use crate::parser::regex::Syntax;
+use crate::parser::types::{CharClass, CharSet, Token};
+
+/// One member of a bracket expression before ranges are put together.
+enum Element {
+    Char(char),
+    Class(CharClass, bool),
+}
+
+/// The element at `chars[i]` and the index after it. With `-P` a backslash
+/// escapes the next char, as outside brackets; in POSIX syntaxes it's literal.
+fn element(chars: &[char], i: usize, syntax: Syntax) -> (Element, usize) {
+    if syntax == Syntax::Perl
+        && chars[i] == '\\'
+        && let Some(&escaped) = chars.get(i + 1)
+    {
+        let element = match escaped {
+            'd' => Element::Class(CharClass::Digit, false),
+            'D' => Element::Class(CharClass::Digit, true),
+            'w' => Element::Class(CharClass::Word, false),
+            'W' => Element::Class(CharClass::Word, true),
+            's' => Element::Class(CharClass::Space, false),
+            'S' => Element::Class(CharClass::Space, true),
+            'n' => Element::Char('\n'),
+            't' => Element::Char('\t'),
+            'r' => Element::Char('\r'),
+            'f' => Element::Char('\x0c'),
+            'v' => Element::Char('\x0b'),
+            'e' => Element::Char('\x1b'),
+            c => Element::Char(c),
+        };
+        return (element, i + 2);
+    }
+    (Element::Char(chars[i]), i + 1)
+}
+
+/// Parses the bracket expression starting at `chars[start] == '['`, returning
+/// its token and the index after its closing `]`, or `None` if it's never
+/// closed.
+///
+/// As POSIX has it, a `]` right after the `[` or `[^` is a member rather than
+/// the end, `a-z` is a range, a `-` first or last is literal, and `[:alpha:]`
+/// and the other classes, `[=c=]` and `[.c.]` can appear among the members.
+/// Unknown classes and reversed ranges match nothing.
+pub fn parse(chars: &[char], start: usize, syntax: Syntax) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let negated = chars.get(i) == Some(&'^');
+    if negated {
+        i += 1;
+    }
+
+    let mut set = CharSet::default();
+    let mut first = true;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == ']' && !first {
+            let token = if negated {
+                Token::NegCharGroup(set)
+            } else {
+                Token::CharGroup(set)
+            };
+            return Some((token, i + 1));
+        }
+        first = false;
+
+        // [:class:], [=c=] and [.c.]
+        if c == '['
+            && let Some(&kind @ (':' | '=' | '.')) = chars.get(i + 1)
+            && let Some(len) = chars[i + 2..]
+                .windows(2)
+                .position(|pair| pair == [kind, ']'])
+        {
+            let name: String = chars[i + 2..i + 2 + len].iter().collect();
+            if kind == ':' {
+                if let Some(class) = CharClass::from_name(&name) {
+                    set.classes.push((class, false));
+                }
+            } else if let [c] = chars[i + 2..i + 2 + len] {
+                set.chars.push(c);
+            }
+            i += len + 4;
+            continue;
+        }
+
+        let (low, next) = element(chars, i, syntax);
+        match low {
+            Element::Class(class, negated) => {
+                set.classes.push((class, negated));
+                i = next;
+            }
+            Element::Char(low)
+                if chars.get(next) == Some(&'-')
+                    && chars.get(next + 1).is_some_and(|&c| c != ']') =>
+            {
+                let (high, after) = element(chars, next + 1, syntax);
+                match high {
+                    Element::Char(high) if low <= high => set.ranges.push((low, high)),
+                    Element::Char(_) => {}
+                    // `a-\d` isn't a range: both ends stand for themselves
+                    Element::Class(class, negated) => {
+                        set.chars.extend([low, '-']);
+                        set.classes.push((class, negated));
+                    }
+                }
+                i = after;
+            }
+            Element::Char(c) => {
+                set.chars.push(c);
+                i = next;
+            }
+        }
+    }
+    None
+}
+
+/// The index after the `]` closing the bracket expression at `chars[start]`.
+pub fn end(chars: &[char], start: usize, syntax: Syntax) -> Option<usize> {
+    parse(chars, start, syntax).map(|(_, end)| end)
+}
@@ -3,22 +3,21 @@ use crate::parser::types::Token;
 pub fn assign_group_numbers(tokens: &mut [Token], group_counter: &mut usize) {
     for token in tokens.iter_mut() {
         match token {
-            Token::CaptureGroup(group_num, inner_tokens) => {
+            Token::CaptureGroup(group_num, _, inner_tokens) => {
                 *group_num = *group_counter;
                 *group_counter += 1;
                 assign_group_numbers(inner_tokens, group_counter);
             }
+            Token::Group(inner_tokens) => assign_group_numbers(inner_tokens, group_counter),
             Token::Alternation(branches) => {
-                // For alternation, we need to handle the case where different branches
-                // might have different numbers of capture groups. This is complex.
-                // For now, let's assign numbers based on the first branch and hope
-                // all branches have the same structure (which they should in valid regex).
-                if let Some(first_branch) = branches.first_mut() {
-                    assign_group_numbers(first_branch, group_counter);
+                // Groups are numbered by their opening parenthesis, across all
+                // branches, as in Perl
+                for branch in branches {
+                    assign_group_numbers(branch, group_counter);
                 }
             }
             Token::Quantifier(inner, _) => {
-                if let Token::CaptureGroup(group_num, inner_tokens) = inner.as_mut() {
+                if let Token::CaptureGroup(group_num, _, inner_tokens) = inner.as_mut() {
                     *group_num = *group_counter;
                     *group_counter += 1;
                     assign_group_numbers(inner_tokens, group_counter);
@@ -27,7 +26,7 @@ pub fn assign_group_numbers(tokens: &mut [Token], group_counter: &mut usize) {
                 }
             }
             Token::ExactRepetition(inner, _) | Token::RangeRepetition(inner, _, _) => {
-                if let Token::CaptureGroup(group_num, inner_tokens) = inner.as_mut() {
+                if let Token::CaptureGroup(group_num, _, inner_tokens) = inner.as_mut() {
                     *group_num = *group_counter;
                     *group_counter += 1;
                     assign_group_numbers(inner_tokens, group_counter);
@@ -39,3 +38,37 @@ pub fn assign_group_numbers(tokens: &mut [Token], group_counter: &mut usize) {
         }
     }
 }
+
+/// Names of the capture groups of numbered `tokens`, indexed by group number
+/// minus one; `None` for unnamed groups.
+pub fn group_names(tokens: &[Token]) -> Vec<Option<String>> {
+    fn collect(tokens: &[Token], names: &mut Vec<Option<String>>) {
+        for token in tokens {
+            match token {
+                Token::CaptureGroup(group_num, name, inner_tokens) => {
+                    if names.len() < *group_num {
+                        names.resize(*group_num, None);
+                    }
+                    names[group_num - 1] = name.clone();
+                    collect(inner_tokens, names);
+                }
+                Token::Group(inner_tokens) => collect(inner_tokens, names),
+                Token::Alternation(branches) => {
+                    for branch in branches {
+                        collect(branch, names);
+                    }
+                }
+                Token::Quantifier(inner, _)
+                | Token::ExactRepetition(inner, _)
+                | Token::RangeRepetition(inner, _, _) => {
+                    collect(std::slice::from_ref(inner.as_ref()), names)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut names = Vec::new();
+    collect(tokens, &mut names);
+    names
+}
@@ -0,0 +1,103 @@
+/// First of the private-use code points standing in for raw bytes. The bytes
+/// 0x80..=0xFF of an invalid UTF-8 sequence map to `RAW_BYTE_BASE + byte`.
+pub const RAW_BYTE_BASE: u32 = 0x10FF00;
+
+/// Returns the stand-in char for a raw byte, as produced by `DecodedLine` and by
+/// `\xHH` escapes in patterns.
+pub fn raw_byte_char(byte: u8) -> char {
+    char::from_u32(RAW_BYTE_BASE + byte as u32).expect("stand-in is a valid code point")
+}
+
+/// A line of raw input decoded for the matcher.
+///
+/// Valid UTF-8 sequences decode to their `char`; every byte of an invalid
+/// sequence decodes to its `raw_byte_char` stand-in, so binary-ish and Latin-1
+/// input can still be matched (by `.`, negated groups or `\xHH`). Each char keeps
+/// the byte offset it was decoded from, so matches map back to the original bytes
+/// for output.
+pub struct DecodedLine<'a> {
+    bytes: &'a [u8],
+    pub chars: Vec<char>,
+    /// Byte offset of each char and of the end, or empty for an ASCII line,
+    /// where they're the char indices
+    offsets: Vec<usize>,
+    /// How many of `chars` the pattern is matched against, see `text`
+    text_len: usize,
+}
+
+impl<'a> DecodedLine<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        if bytes.is_ascii() {
+            return DecodedLine {
+                bytes,
+                text_len: bytes.len(),
+                chars: bytes.iter().map(|&byte| byte as char).collect(),
+                offsets: Vec::new(),
+            };
+        }
+
+        let mut chars = Vec::with_capacity(bytes.len());
+        let mut offsets = Vec::with_capacity(bytes.len() + 1);
+        let mut pos = 0;
+
+        while pos < bytes.len() {
+            let (valid, invalid_len) = match std::str::from_utf8(&bytes[pos..]) {
+                Ok(valid) => (valid, 0),
+                Err(e) => {
+                    let valid_len = e.valid_up_to();
+                    let valid = std::str::from_utf8(&bytes[pos..pos + valid_len])
+                        .expect("prefix was validated");
+                    // A truncated sequence at the very end has no error_len
+                    let invalid_len = e.error_len().unwrap_or(bytes.len() - pos - valid_len);
+                    (valid, invalid_len)
+                }
+            };
+
+            for (i, c) in valid.char_indices() {
+                offsets.push(pos + i);
+                chars.push(c);
+            }
+            pos += valid.len();
+
+            for &byte in &bytes[pos..pos + invalid_len] {
+                offsets.push(pos);
+                chars.push(raw_byte_char(byte));
+                pos += 1;
+            }
+        }
+        offsets.push(bytes.len());
+
+        DecodedLine {
+            bytes,
+            text_len: chars.len(),
+            chars,
+            offsets,
+        }
+    }
+
+    /// With `crlf`, leaves a final `\r` out of `text`: it's part of the line's
+    /// `\r\n` terminator, but is still printed with the line.
+    pub fn with_crlf(mut self, crlf: bool) -> Self {
+        if crlf && self.chars.last() == Some(&'\r') {
+            self.text_len -= 1;
+        }
+        self
+    }
+
+    /// The chars the pattern is matched against.
+    pub fn text(&self) -> &[char] {
+        &self.chars[..self.text_len]
+    }
+
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The original bytes of the chars `start..end`.
+    pub fn slice(&self, start: usize, end: usize) -> &'a [u8] {
+        if self.offsets.is_empty() {
+            return &self.bytes[start..end];
+        }
+        &self.bytes[self.offsets[start]..self.offsets[end]]
+    }
+}
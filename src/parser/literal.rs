@@ -0,0 +1,70 @@
+use crate::parser::input::RAW_BYTE_BASE;
+use crate::parser::types::Token;
+
+/// Runs of literal chars that every match of a token sequence contains, in the
+/// order they come in.
+#[derive(Default)]
+struct Runs {
+    done: Vec<String>,
+    current: String,
+}
+
+impl Runs {
+    fn push(&mut self, c: char) {
+        // Stand-ins for raw bytes don't match their own UTF-8 encoding
+        if c as u32 >= RAW_BYTE_BASE {
+            self.end();
+        } else {
+            self.current.push(c);
+        }
+    }
+
+    /// Ends the current run at a token that isn't a fixed literal.
+    fn end(&mut self) {
+        if !self.current.is_empty() {
+            self.done.push(std::mem::take(&mut self.current));
+        }
+    }
+
+    fn add(&mut self, tokens: &[Token]) {
+        for token in tokens {
+            match token {
+                Token::Literal(c) => self.push(*c),
+                // Groups are matched exactly once, so their literals join the
+                // ones around them
+                Token::CaptureGroup(_, _, inner) | Token::Group(inner) => self.add(inner),
+                Token::ExactRepetition(inner, count) if matches!(**inner, Token::Literal(_)) => {
+                    for _ in 0..*count {
+                        self.add(std::slice::from_ref(inner));
+                    }
+                }
+                // `o+` is one `o` that more may follow: `fo+bar` needs `fo` and `obar`
+                Token::Quantifier(inner, quantifier)
+                    if quantifier.at_least_one() && matches!(**inner, Token::Literal(_)) =>
+                {
+                    self.add(std::slice::from_ref(inner));
+                    self.end();
+                    self.add(std::slice::from_ref(inner));
+                }
+                // Anchors match no chars, so they don't break a run
+                Token::StartAnchor
+                | Token::EndAnchor
+                | Token::LineStart
+                | Token::LineEnd
+                | Token::CrlfLineEnd => {}
+                _ => self.end(),
+            }
+        }
+    }
+}
+
+/// The longest run of literal chars every match of `tokens` contains, if any.
+///
+/// A line's raw bytes can only match if they contain its UTF-8 encoding, so
+/// looking for it first lets most lines be ruled out without decoding them.
+pub fn required(tokens: &[Token]) -> Option<String> {
+    let mut runs = Runs::default();
+    runs.add(tokens);
+    runs.end();
+    runs.done.into_iter().max_by_key(|run| run.len())
+}
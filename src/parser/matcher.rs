@@ -1,5 +1,5 @@
 use crate::core::Config;
-use crate::parser::types::Token;
+use crate::parser::types::{Span, Token};
 
 /// Recursive function similar to matches_from, but returns the length of the match if successful
 pub fn matches_from_range(
@@ -7,20 +7,16 @@ pub fn matches_from_range(
     tokens: &[Token],
     input_index: usize,
     config: Option<&Config>,
-    captures: &mut Vec<Option<String>>,
+    captures: &mut Vec<Option<Span>>,
 ) -> Option<usize> {
     // Log iteration header
-    unsafe {
-        if crate::core::ITERATION_COUNT <= crate::core::MAX_ITERATIONS {
-            crate::core::log_iteration_header(config, crate::core::ITERATION_COUNT);
-        }
+    let iteration = crate::core::ITERATION_COUNT.load(std::sync::atomic::Ordering::Relaxed);
+    if iteration <= crate::core::MAX_ITERATIONS {
+        crate::core::log_iteration_header(config, iteration);
     }
 
     if tokens.is_empty() {
-        crate::core::debug_log(
-            config,
-            &format!("matches_from_range: empty tokens, returning 0"),
-        );
+        crate::core::debug_log(config, "matches_from_range: empty tokens, returning 0");
         return Some(0);
     }
 
@@ -40,10 +36,10 @@ pub fn matches_from_range(
     match token {
         Token::StartAnchor => {
             if input_index != 0 {
-                crate::core::debug_log(config, &format!("StartAnchor failed: input_index != 0"));
+                crate::core::debug_log(config, "StartAnchor failed: input_index != 0");
                 return None;
             }
-            crate::core::debug_log(config, &format!("StartAnchor matched"));
+            crate::core::debug_log(config, "StartAnchor matched");
             matches_from_range(input, &tokens[1..], input_index, config, captures)
         }
         Token::EndAnchor => {
@@ -58,139 +54,71 @@ pub fn matches_from_range(
                 );
                 return None;
             }
-            crate::core::debug_log(config, &format!("EndAnchor matched"));
+            crate::core::debug_log(config, "EndAnchor matched");
             matches_from_range(input, &tokens[1..], input_index, config, captures)
         }
-        Token::Quantifier(inner, quant) => match quant {
-            crate::parser::types::Quantifiers::OneOrMore => {
-                crate::core::debug_log(config, &format!("Quantifier OneOrMore: inner={:?}", inner));
-                // Collect all possible match positions
-                let mut positions = vec![input_index];
-                let mut current_pos = input_index;
-                while let Some(len) = matches_from_range(
+        Token::LineStart | Token::LineEnd | Token::CrlfLineEnd => {
+            if !line_anchor_holds(token, input, input_index) {
+                crate::core::debug_log(config, &format!("{:?} failed", token));
+                return None;
+            }
+            matches_from_range(input, &tokens[1..], input_index, config, captures)
+        }
+        Token::Quantifier(inner, quant) => {
+            crate::core::debug_log(
+                config,
+                &format!("Quantifier {:?}: inner={:?}", quant, inner),
+            );
+            // Collect every position the repetition can stop at, fewest first
+            let mut positions = vec![input_index];
+            let mut current_pos = input_index;
+            while !(quant.at_most_one() && positions.len() > 1) {
+                let Some(len) = matches_from_range(
                     input,
                     &[inner.as_ref().clone()],
                     current_pos,
                     config,
                     captures,
-                ) {
-                    crate::core::debug_log(
-                        config,
-                        &format!("OneOrMore: matched inner at {}, len={}", current_pos, len),
-                    );
-                    current_pos += len;
-                    positions.push(current_pos);
-                }
-                crate::core::debug_log(config, &format!("OneOrMore positions: {:?}", positions));
-
-                if positions.len() <= 1 {
-                    crate::core::debug_log(config, &format!("OneOrMore failed: no matches"));
-                    return None; // need at least 1 match
-                }
-
-                // Try with different numbers of matches (longest first - greedy)
-                for &pos in positions.iter().rev() {
-                    // try longest matches first
-                    if pos == input_index {
-                        continue;
-                    } // skip 0 matches
-                    crate::core::debug_log(config, &format!("OneOrMore trying pos={}", pos));
-                    let saved_captures = captures.clone();
-                    if let Some(rest_len) =
-                        matches_from_range(input, &tokens[1..], pos, config, captures)
-                    {
-                        let total = pos - input_index + rest_len;
-                        crate::core::debug_log(
-                            config,
-                            &format!("OneOrMore success: total_len={}", total),
-                        );
-                        return Some(total);
-                    } else {
-                        *captures = saved_captures;
-                    }
-                }
-                crate::core::debug_log(config, &format!("OneOrMore failed: no rest match"));
-                None
-            }
-            crate::parser::types::Quantifiers::ZeroOrMore => {
+                ) else {
+                    break;
+                };
                 crate::core::debug_log(
                     config,
-                    &format!("Quantifier ZeroOrMore: inner={:?}", inner),
+                    &format!("Quantifier: matched inner at {}, len={}", current_pos, len),
                 );
-                // Collect all possible match positions (including 0 matches)
-                let mut positions = vec![input_index];
-                let mut current_pos = input_index;
-                while let Some(len) = matches_from_range(
-                    input,
-                    &[inner.as_ref().clone()],
-                    current_pos,
-                    config,
-                    captures,
-                ) {
-                    crate::core::debug_log(
-                        config,
-                        &format!("ZeroOrMore: matched inner at {}, len={}", current_pos, len),
-                    );
-                    current_pos += len;
-                    positions.push(current_pos);
-                }
-                crate::core::debug_log(config, &format!("ZeroOrMore positions: {:?}", positions));
-
-                // Try with different numbers of matches (longest first - greedy)
-                for &pos in positions.iter().rev() {
-                    crate::core::debug_log(config, &format!("ZeroOrMore trying pos={}", pos));
-                    let saved_captures = captures.clone();
-                    if let Some(rest_len) =
-                        matches_from_range(input, &tokens[1..], pos, config, captures)
-                    {
-                        let total = pos - input_index + rest_len;
-                        crate::core::debug_log(
-                            config,
-                            &format!("ZeroOrMore success: total_len={}", total),
-                        );
-                        return Some(total);
-                    } else {
-                        *captures = saved_captures;
-                    }
+                current_pos += len;
+                positions.push(current_pos);
+                // Repeating an empty match can't get any further
+                if len == 0 {
+                    break;
                 }
-                crate::core::debug_log(config, &format!("ZeroOrMore failed: no rest match"));
-                None
             }
-            crate::parser::types::Quantifiers::ZeroOrOne => {
-                crate::core::debug_log(config, &format!("Quantifier ZeroOrOne: inner={:?}", inner));
-                // try 1 match
+            if quant.at_least_one() {
+                positions.remove(0);
+            }
+            // Greedy quantifiers try the most repetitions first, lazy ones the fewest
+            if !quant.is_lazy() {
+                positions.reverse();
+            }
+            crate::core::debug_log(config, &format!("Quantifier positions: {:?}", positions));
+
+            for pos in positions {
                 let saved_captures = captures.clone();
-                if let Some(len) = matches_from_range(
-                    input,
-                    &[inner.as_ref().clone()],
-                    input_index,
-                    config,
-                    captures,
-                ) {
+                if let Some(rest_len) =
+                    matches_from_range(input, &tokens[1..], pos, config, captures)
+                {
+                    let total = pos - input_index + rest_len;
                     crate::core::debug_log(
                         config,
-                        &format!("ZeroOrOne: trying 1 match, len={}", len),
+                        &format!("Quantifier success: total_len={}", total),
                     );
-                    if let Some(rest_len) =
-                        matches_from_range(input, &tokens[1..], input_index + len, config, captures)
-                    {
-                        let total = len + rest_len;
-                        crate::core::debug_log(
-                            config,
-                            &format!("ZeroOrOne success with 1 match: total_len={}", total),
-                        );
-                        return Some(total);
-                    } else {
-                        *captures = saved_captures;
-                    }
-                } else {
-                    *captures = saved_captures;
+                    return Some(total);
                 }
-                // try 0 match
-                crate::core::debug_log(config, &format!("ZeroOrOne: trying 0 match"));
-                matches_from_range(input, &tokens[1..], input_index, config, captures)
+                *captures = saved_captures;
             }
-        },
+            crate::core::debug_log(config, "Quantifier failed: no rest match");
+            None
+        }
         Token::ExactRepetition(inner, count) => {
             crate::core::debug_log(
                 config,
@@ -231,10 +159,7 @@ pub fn matches_from_range(
             {
                 Some(current_pos - input_index + rest_len)
             } else {
-                crate::core::debug_log(
-                    config,
-                    &format!("ExactRepetition failed: rest didn't match"),
-                );
+                crate::core::debug_log(config, "ExactRepetition failed: rest didn't match");
                 None
             }
         }
@@ -307,7 +232,7 @@ pub fn matches_from_range(
                     }
                 }
             } else {
-                // {n,} - match as many as possible
+                // {n,} - match as many as possible, short of repeating an empty match
                 while let Some(len) = matches_from_range(
                     input,
                     &[inner.as_ref().clone()],
@@ -325,6 +250,9 @@ pub fn matches_from_range(
                     );
                     current_pos += len;
                     count += 1;
+                    if len == 0 {
+                        break;
+                    }
                 }
             }
 
@@ -340,10 +268,7 @@ pub fn matches_from_range(
             {
                 Some(current_pos - input_index + rest_len)
             } else {
-                crate::core::debug_log(
-                    config,
-                    &format!("RangeRepetition failed: rest didn't match"),
-                );
+                crate::core::debug_log(config, "RangeRepetition failed: rest didn't match");
                 None
             }
         }
@@ -366,10 +291,16 @@ pub fn matches_from_range(
                     *captures = saved_captures;
                 }
             }
-            crate::core::debug_log(config, &format!("Alternation failed"));
+            crate::core::debug_log(config, "Alternation failed");
             None
         }
-        Token::CaptureGroup(group_num, inner_tokens) => {
+        Token::Group(inner_tokens) => {
+            // Without a capture to record, the group's tokens simply come first
+            let mut combined = inner_tokens.clone();
+            combined.extend_from_slice(&tokens[1..]);
+            matches_from_range(input, &combined, input_index, config, captures)
+        }
+        Token::CaptureGroup(group_num, _, inner_tokens) => {
             crate::core::debug_log(
                 config,
                 &format!("CaptureGroup {}: matching inner tokens", group_num),
@@ -396,7 +327,7 @@ pub fn matches_from_range(
                 }
                 // Capture the matched substring
                 let matched_str: String = input[input_index..input_index + len].iter().collect();
-                captures[capture_index] = Some(matched_str.clone());
+                captures[capture_index] = Some((input_index, input_index + len));
                 crate::core::debug_log(
                     config,
                     &format!(
@@ -446,14 +377,12 @@ pub fn matches_from_range(
         Token::BackReference(n) => {
             crate::core::debug_log(config, &format!("BackReference: checking capture {}", n));
             if input_index >= input.len() {
-                crate::core::debug_log(
-                    config,
-                    &format!("BackReference failed: input_index >= input.len()"),
-                );
+                crate::core::debug_log(config, "BackReference failed: input_index >= input.len()");
                 return None;
             }
-            if let Some(Some(captured)) = captures.get(n - 1) {
-                let captured_chars: Vec<char> = captured.chars().collect();
+            if let Some(&Some((start, end))) = captures.get(n - 1) {
+                let captured_chars = &input[start..end];
+                let captured: String = captured_chars.iter().collect();
                 crate::core::debug_log(
                     config,
                     &format!(
@@ -461,7 +390,7 @@ pub fn matches_from_range(
                         captured, input_index
                     ),
                 );
-                if input[input_index..].starts_with(&captured_chars) {
+                if input[input_index..].starts_with(captured_chars) {
                     let len = captured_chars.len();
                     crate::core::debug_log(
                         config,
@@ -472,10 +401,7 @@ pub fn matches_from_range(
                     {
                         Some(len + rest_len)
                     } else {
-                        crate::core::debug_log(
-                            config,
-                            &format!("BackReference failed: rest didn't match"),
-                        );
+                        crate::core::debug_log(config, "BackReference failed: rest didn't match");
                         None
                     }
                 } else {
@@ -518,44 +444,32 @@ pub fn matches_from_range(
             if let Some(rest_len) =
                 matches_from_range(input, &tokens[1..], input_index + 1, config, captures)
             {
-                return Some(1 + rest_len);
+                Some(1 + rest_len)
             } else {
                 crate::core::debug_log(
                     config,
                     &format!("Token {:?} failed: rest didn't match", token),
                 );
-                return None;
+                None
             }
         }
     }
 }
 
-pub fn match_tokens(input: &str, tokens: &[Token], config: &Config) -> bool {
-    let input_chars: Vec<char> = input.chars().collect();
-
-    // Respect ^ anchor
-    let start_positions = if matches!(tokens.first(), Some(Token::StartAnchor)) {
-        vec![0]
-    } else {
-        (0..=input_chars.len()).collect()
-    };
-
-    for &start_index in &start_positions {
-        let mut captures = Vec::new();
-        if matches_from_range(
-            &input_chars,
-            tokens,
-            start_index,
-            Some(config),
-            &mut captures,
-        )
-        .is_some()
-        {
-            return true;
-        }
+/// Whether the `(?m)` anchor `token` holds at `pos`: `^` at the start of the
+/// input or after a newline, `$` at the end or before one. With `--crlf`, `$`
+/// goes before the `\r` of a `\r\n` instead of between the two.
+pub fn line_anchor_holds(token: &Token, input: &[char], pos: usize) -> bool {
+    match token {
+        Token::LineStart => pos == 0 || input[pos - 1] == '\n',
+        Token::LineEnd => pos == input.len() || input[pos] == '\n',
+        Token::CrlfLineEnd => match &input[pos..] {
+            [] | ['\n', ..] => pos == 0 || input[pos - 1] != '\r',
+            ['\r'] | ['\r', '\n', ..] => true,
+            _ => false,
+        },
+        _ => unreachable!("not a line anchor"),
     }
-
-    false
 }
 
 pub fn single_matches(input: &[char], token: &Token) -> bool {
@@ -567,14 +481,23 @@ pub fn single_matches(input: &[char], token: &Token) -> bool {
     match token {
         Token::Digit => ch.is_ascii_digit(),
         Token::Word => ch.is_ascii_alphanumeric() || ch == '_',
+        Token::Space => ch.is_ascii_whitespace() || ch == '\x0b',
+        Token::Negated(inner) => !single_matches(input, inner),
         Token::Literal(c) => *c == ch,
-        Token::CharGroup(set) => set.contains(&ch),
-        Token::NegCharGroup(set) => !set.contains(&ch),
-        Token::StartAnchor | Token::EndAnchor => true, // handled in matches_from
+        Token::CharGroup(set) => set.contains(ch),
+        Token::NegCharGroup(set) => !set.contains(ch),
+        Token::AnyChar => true,
+        Token::StartAnchor
+        | Token::EndAnchor
+        | Token::LineStart
+        | Token::LineEnd
+        | Token::CrlfLineEnd => {
+            true // handled in matches_from
+        }
         Token::WildCard => ch != '\n',
         Token::Quantifier(_, _) => unreachable!("Quantifier handled in matches_from"),
         Token::Alternation(_) => unreachable!("handled in matches_from"),
-        Token::CaptureGroup(_, _) => unreachable!("CaptureGroup handled in matches_from"),
+        Token::CaptureGroup(..) | Token::Group(_) => unreachable!("groups handled in matches_from"),
         Token::BackReference(_) => unreachable!("BackReference handled in matches_from"),
         Token::ExactRepetition(_, _) => unreachable!("ExactRepetition handled in matches_from"),
         Token::RangeRepetition(_, _, _) => unreachable!("RangeRepetition handled in matches_from"),
@@ -589,7 +512,7 @@ fn collect_all_match_lengths(
     tokens: &[Token],
     input_index: usize,
     config: Option<&Config>,
-    captures: &Vec<Option<String>>,
+    captures: &Vec<Option<Span>>,
 ) -> Vec<usize> {
     if tokens.is_empty() {
         return vec![0];
@@ -623,10 +546,22 @@ fn collect_all_match_lengths(
             }
             return vec![];
         }
+        Token::LineStart | Token::LineEnd | Token::CrlfLineEnd => {
+            if line_anchor_holds(token, input, input_index) {
+                return collect_all_match_lengths(
+                    input,
+                    &tokens[1..],
+                    input_index,
+                    config,
+                    captures,
+                );
+            }
+            return vec![];
+        }
         Token::Quantifier(inner, quant) => {
             // Collect all positions where the quantifier can stop
             let mut positions = vec![input_index]; // For * and ?, include 0 matches
-            if matches!(quant, crate::parser::types::Quantifiers::OneOrMore) {
+            if quant.at_least_one() {
                 positions.clear(); // For +, must have at least 1 match
             }
 
@@ -641,10 +576,8 @@ fn collect_all_match_lengths(
             ) {
                 current_pos += len;
                 positions.push(current_pos);
-                if matches!(quant, crate::parser::types::Quantifiers::ZeroOrOne)
-                    && positions.len() > 1
-                {
-                    break; // ? matches at most once
+                if quant.at_most_one() || len == 0 {
+                    break; // ? matches at most once, and empty repetitions go nowhere
                 }
             }
 
@@ -666,19 +599,22 @@ fn collect_all_match_lengths(
                 result.extend(branch_lengths);
             }
         }
-        Token::CaptureGroup(group_num, inner_tokens) => {
+        Token::Group(inner_tokens) => {
+            let mut combined = inner_tokens.clone();
+            combined.extend_from_slice(&tokens[1..]);
+            return collect_all_match_lengths(input, &combined, input_index, config, captures);
+        }
+        Token::CaptureGroup(group_num, _, inner_tokens) => {
             let group_num = *group_num;
             // Collect all possible lengths for inner tokens
             let inner_lengths =
                 collect_all_match_lengths(input, inner_tokens, input_index, config, captures);
             for inner_len in inner_lengths {
                 let mut temp_captures = captures.clone();
-                let matched_str: String =
-                    input[input_index..input_index + inner_len].iter().collect();
                 if group_num > temp_captures.len() {
                     temp_captures.resize(group_num, None);
                 }
-                temp_captures[group_num - 1] = Some(matched_str);
+                temp_captures[group_num - 1] = Some((input_index, input_index + inner_len));
                 // Populate nested captures by running dummy inner match
                 let mut dummy_captures = temp_captures.clone();
                 let _ = matches_from_range(
@@ -703,10 +639,10 @@ fn collect_all_match_lengths(
         }
         Token::BackReference(n) => {
             let n = *n;
-            if let Some(Some(captured)) = captures.get(n - 1) {
-                let captured_chars: Vec<char> = captured.chars().collect();
+            if let Some(&Some((start, end))) = captures.get(n - 1) {
+                let captured_chars = &input[start..end];
                 if input_index + captured_chars.len() <= input.len()
-                    && input[input_index..input_index + captured_chars.len()] == captured_chars
+                    && input[input_index..input_index + captured_chars.len()] == *captured_chars
                 {
                     let len = captured_chars.len();
                     let rest_lengths = collect_all_match_lengths(
@@ -802,6 +738,9 @@ fn collect_all_match_lengths(
                         current_pos += len;
                         count += 1;
                         positions.push(current_pos);
+                        if len == 0 {
+                            break;
+                        }
                     }
                 }
 
@@ -815,7 +754,7 @@ fn collect_all_match_lengths(
             }
         }
         _ => {
-            // Simple tokens: Literal, Digit, Word, CharGroup, NegCharGroup, WildCard
+            // Simple tokens: Literal, Digit, Word, Space, Negated, CharGroup, NegCharGroup, WildCard
             if input_index >= input.len() {
                 return vec![];
             }
@@ -1,5 +1,10 @@
+pub mod basic;
+pub mod bracket;
 pub mod groups;
+pub mod input;
+pub mod literal;
 pub mod matcher;
-pub mod token;
+pub mod nfa;
+pub mod regex;
 pub mod tokenize;
 pub mod types;
@@ -0,0 +1,355 @@
+use crate::parser::matcher::{line_anchor_holds, single_matches};
+use crate::parser::regex::Captures;
+use crate::parser::types::{Quantifiers, Token};
+
+/// One instruction of a compiled pattern.
+#[derive(Debug, Clone)]
+enum Inst {
+    /// Consumes one char matching a simple token (literal, class, `.`)
+    Char(Token),
+    /// Continues at both targets, the first with higher priority
+    Split(usize, usize),
+    Jump(usize),
+    /// Records the current position in a capture slot: `2 * (group - 1)` for a
+    /// group's start and one more for its end
+    Save(usize),
+    StartAnchor,
+    EndAnchor,
+    /// `^` or `$` with `(?m)`
+    LineAnchor(Token),
+    Match,
+}
+
+/// A pattern compiled for a Thompson NFA simulation, which runs in time linear
+/// in the input for each start position it's still considering.
+///
+/// Threads are kept in priority order, the order a backtracking matcher would
+/// try them in, so the simulation finds either the leftmost-longest match POSIX
+/// asks for (`-G`, `-E`) or the leftmost-first one Perl does (`-P`).
+/// Backreferences can't be matched this way, so `compile` returns `None` for
+/// patterns that use them, and they keep the backtracking matcher.
+#[derive(Debug, Clone)]
+pub struct Program {
+    insts: Vec<Inst>,
+    longest: bool,
+}
+
+/// A thread of the simulation: where it is in the program, where its match
+/// started and the capture slots it has recorded.
+struct Thread {
+    pc: usize,
+    start: usize,
+    slots: Vec<Option<usize>>,
+}
+
+impl Program {
+    pub fn compile(tokens: &[Token], longest: bool) -> Option<Program> {
+        let mut program = Program {
+            insts: Vec::new(),
+            longest,
+        };
+        program.sequence(tokens)?;
+        program.insts.push(Inst::Match);
+        Some(program)
+    }
+
+    fn sequence(&mut self, tokens: &[Token]) -> Option<()> {
+        tokens.iter().try_for_each(|token| self.token(token))
+    }
+
+    fn push(&mut self, inst: Inst) -> usize {
+        self.insts.push(inst);
+        self.insts.len() - 1
+    }
+
+    /// Points the placeholder `Split` or `Jump` at `at` to `target`; a split's
+    /// second branch is the one left open.
+    fn patch(&mut self, at: usize, target: usize) {
+        match &mut self.insts[at] {
+            Inst::Split(_, second) => *second = target,
+            Inst::Jump(to) => *to = target,
+            _ => unreachable!("only splits and jumps are patched"),
+        }
+    }
+
+    /// `token`, optionally: a split that either runs it or skips it.
+    fn optional(&mut self, token: &Token, lazy: bool) -> Option<()> {
+        let split = self.push(Inst::Split(0, 0));
+        self.token(token)?;
+        let end = self.insts.len();
+        self.insts[split] = if lazy {
+            Inst::Split(end, split + 1)
+        } else {
+            Inst::Split(split + 1, end)
+        };
+        Some(())
+    }
+
+    /// `token` repeated any number of times.
+    fn star(&mut self, token: &Token, lazy: bool) -> Option<()> {
+        let split = self.push(Inst::Split(0, 0));
+        self.token(token)?;
+        self.push(Inst::Jump(split));
+        let end = self.insts.len();
+        self.insts[split] = if lazy {
+            Inst::Split(end, split + 1)
+        } else {
+            Inst::Split(split + 1, end)
+        };
+        Some(())
+    }
+
+    fn token(&mut self, token: &Token) -> Option<()> {
+        match token {
+            Token::StartAnchor => {
+                self.push(Inst::StartAnchor);
+            }
+            Token::EndAnchor => {
+                self.push(Inst::EndAnchor);
+            }
+            Token::LineStart | Token::LineEnd | Token::CrlfLineEnd => {
+                self.push(Inst::LineAnchor(token.clone()));
+            }
+            Token::BackReference(_) => return None,
+            Token::Group(tokens) => self.sequence(tokens)?,
+            Token::CaptureGroup(group, _, tokens) => {
+                self.push(Inst::Save(2 * (group - 1)));
+                self.sequence(tokens)?;
+                self.push(Inst::Save(2 * (group - 1) + 1));
+            }
+            Token::Alternation(branches) => {
+                // Each branch but the last is tried through a split, and all of
+                // them jump to the end when done
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.push(Inst::Split(self.insts.len() + 1, 0));
+                        self.sequence(branch)?;
+                        jumps.push(self.push(Inst::Jump(0)));
+                        let next = self.insts.len();
+                        self.patch(split, next);
+                    } else {
+                        self.sequence(branch)?;
+                    }
+                }
+                let end = self.insts.len();
+                for jump in jumps {
+                    self.patch(jump, end);
+                }
+            }
+            Token::Quantifier(inner, quantifier) => {
+                let lazy = quantifier.is_lazy();
+                match quantifier {
+                    Quantifiers::ZeroOrOne | Quantifiers::LazyZeroOrOne => {
+                        self.optional(inner, lazy)?
+                    }
+                    Quantifiers::ZeroOrMore | Quantifiers::LazyZeroOrMore => {
+                        self.star(inner, lazy)?
+                    }
+                    Quantifiers::OneOrMore | Quantifiers::LazyOneOrMore => {
+                        self.token(inner)?;
+                        self.star(inner, lazy)?;
+                    }
+                }
+            }
+            Token::ExactRepetition(inner, count) => {
+                for _ in 0..*count {
+                    self.token(inner)?;
+                }
+            }
+            Token::RangeRepetition(inner, min, max) => {
+                for _ in 0..*min {
+                    self.token(inner)?;
+                }
+                match max {
+                    // Nested, as in x{1,3} = x(x(x)?)?, so an optional copy
+                    // only runs after the one before it
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(self.insts.len() + 1, 0)));
+                            self.token(inner)?;
+                        }
+                        let end = self.insts.len();
+                        for split in splits {
+                            self.patch(split, end);
+                        }
+                    }
+                    None => self.star(inner, false)?,
+                }
+            }
+            _ => {
+                self.push(Inst::Char(token.clone()));
+            }
+        }
+        Some(())
+    }
+
+    /// Adds the thread at `pc` to `list`, following splits, jumps, saves and
+    /// anchors, unless a thread of higher priority already got to `pc`.
+    fn add_thread(
+        &self,
+        list: &mut Vec<Thread>,
+        seen: &mut [bool],
+        input: &[char],
+        pos: usize,
+        thread: Thread,
+    ) {
+        if seen[thread.pc] {
+            return;
+        }
+        seen[thread.pc] = true;
+        let pc = thread.pc;
+        match &self.insts[pc] {
+            Inst::Jump(to) => self.add_thread(list, seen, input, pos, Thread { pc: *to, ..thread }),
+            Inst::Split(first, second) => {
+                let other = Thread {
+                    pc: *second,
+                    start: thread.start,
+                    slots: thread.slots.clone(),
+                };
+                self.add_thread(
+                    list,
+                    seen,
+                    input,
+                    pos,
+                    Thread {
+                        pc: *first,
+                        ..thread
+                    },
+                );
+                self.add_thread(list, seen, input, pos, other);
+            }
+            Inst::Save(slot) => {
+                let mut thread = Thread {
+                    pc: pc + 1,
+                    ..thread
+                };
+                thread.slots[*slot] = Some(pos);
+                self.add_thread(list, seen, input, pos, thread);
+            }
+            Inst::StartAnchor if pos == 0 => self.add_thread(
+                list,
+                seen,
+                input,
+                pos,
+                Thread {
+                    pc: pc + 1,
+                    ..thread
+                },
+            ),
+            Inst::EndAnchor if pos == input.len() => self.add_thread(
+                list,
+                seen,
+                input,
+                pos,
+                Thread {
+                    pc: pc + 1,
+                    ..thread
+                },
+            ),
+            Inst::LineAnchor(anchor) if line_anchor_holds(anchor, input, pos) => self.add_thread(
+                list,
+                seen,
+                input,
+                pos,
+                Thread {
+                    pc: pc + 1,
+                    ..thread
+                },
+            ),
+            Inst::StartAnchor | Inst::EndAnchor | Inst::LineAnchor(_) => {}
+            Inst::Char(_) | Inst::Match => list.push(thread),
+        }
+    }
+
+    /// The leftmost-longest or leftmost-first match starting at or after char
+    /// `start` of `input`, with the spans of its `groups` capture groups. Of the
+    /// ways to match the same text, groups are taken from the one with the
+    /// highest priority.
+    pub fn find_at(&self, input: &[char], start: usize, groups: usize) -> Option<Captures> {
+        if start > input.len() {
+            return None;
+        }
+        let mut current: Vec<Thread> = Vec::new();
+        let mut best: Option<(usize, usize, Vec<Option<usize>>)> = None;
+        // The instructions visited while building the current and next lists
+        let mut seen = vec![false; self.insts.len()];
+        let mut next_seen = vec![false; self.insts.len()];
+        let mut pos = start;
+        loop {
+            // A new match can only start while none has been found: any match
+            // found later would start further right
+            if best.is_none() {
+                let thread = Thread {
+                    pc: 0,
+                    start: pos,
+                    slots: vec![None; 2 * groups],
+                };
+                self.add_thread(&mut current, &mut seen, input, pos, thread);
+            }
+            if current.is_empty() && best.is_some() {
+                break;
+            }
+
+            let mut next = Vec::new();
+            for thread in current {
+                if best
+                    .as_ref()
+                    .is_some_and(|(best_start, ..)| thread.start > *best_start)
+                {
+                    continue;
+                }
+                match &self.insts[thread.pc] {
+                    // Threads after this one have lower priority
+                    Inst::Match if !self.longest => {
+                        best = Some((thread.start, pos, thread.slots));
+                        break;
+                    }
+                    Inst::Match => {
+                        let better = match &best {
+                            None => true,
+                            Some((best_start, best_end, _)) => {
+                                thread.start < *best_start
+                                    || (thread.start == *best_start && pos > *best_end)
+                            }
+                        };
+                        if better {
+                            best = Some((thread.start, pos, thread.slots));
+                        }
+                    }
+                    Inst::Char(token) => {
+                        if pos < input.len() && single_matches(&input[pos..], token) {
+                            let pc = thread.pc + 1;
+                            self.add_thread(
+                                &mut next,
+                                &mut next_seen,
+                                input,
+                                pos + 1,
+                                Thread { pc, ..thread },
+                            );
+                        }
+                    }
+                    _ => unreachable!("only chars and matches are left in the list"),
+                }
+            }
+            if pos == input.len() {
+                break;
+            }
+            current = next;
+            std::mem::swap(&mut seen, &mut next_seen);
+            next_seen.fill(false);
+            pos += 1;
+        }
+
+        let (start, end, slots) = best?;
+        let groups = slots
+            .chunks(2)
+            .map(|slot| match slot {
+                [Some(start), Some(end)] => Some((*start, *end)),
+                _ => None,
+            })
+            .collect();
+        Some(((start, end), groups))
+    }
+}
@@ -0,0 +1,220 @@
+use crate::core::Config;
+use crate::parser::basic;
+use crate::parser::groups::{assign_group_numbers, group_names};
+use crate::parser::literal;
+use crate::parser::matcher::matches_from_range;
+use crate::parser::nfa::Program;
+use crate::parser::tokenize::tokenize;
+use crate::parser::types::{CharSet, Span, Token};
+
+/// Pattern syntax, chosen on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// `-G` (the default): basic regular expressions, see `basic::to_extended`
+    Basic,
+    /// `-E`: extended regular expressions
+    Extended,
+    /// `-P`: Perl-compatible, adding lazy quantifiers (`*?`, `+?`, `??`),
+    /// non-capturing groups (`(?:...)`) and the escapes `\s`, `\S`, `\D`, `\W`,
+    /// `\t`, `\r`, `\f`, `\v` and `\e`
+    Perl,
+}
+
+/// Char span of one match and the span of every capture group in it (`None` for
+/// groups that didn't take part).
+pub type Captures = ((usize, usize), Vec<Option<Span>>);
+
+/// A pattern, tokenized and numbered once for the whole search.
+///
+/// `-G` and `-E` patterns find the leftmost-longest match, as POSIX specifies,
+/// and `-P` ones the leftmost-first, the first a backtracking search would come
+/// across: `a|ab` matches `ab` in `abc` with `-E` and `a` with `-P`. Both run
+/// on an NFA `Program`, except for patterns with backreferences, which need the
+/// backtracking matcher.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    tokens: Vec<Token>,
+    names: Vec<Option<String>>,
+    program: Option<Program>,
+    /// Finds the literal every match contains, see `literal::required`
+    literal: Option<memchr::memmem::Finder<'static>>,
+}
+
+/// Splits the flags off a `-P` pattern starting with `(?s)`, `(?m)` or both (as
+/// `(?sm)`): `s` lets `.` match newlines and `m` makes `^` and `$` match at line
+/// boundaries, which only makes a difference with `-U`. Like `(?:...)`, they're
+/// Perl syntax; in a basic regex they're literal text.
+fn inline_flags(pattern: &str) -> (bool, bool, &str) {
+    if let Some(rest) = pattern.strip_prefix("(?")
+        && let Some((flags, rest)) = rest.split_once(')')
+        && !flags.is_empty()
+        && flags.chars().all(|c| c == 's' || c == 'm')
+    {
+        return (flags.contains('s'), flags.contains('m'), rest);
+    }
+    (false, false, pattern)
+}
+
+/// Which forms of `.`, `^` and `$` a pattern uses.
+#[derive(Clone, Copy)]
+struct Flags {
+    /// `(?s)`
+    dot_all: bool,
+    /// `(?m)`
+    multi_line: bool,
+    /// `--crlf`: `.` doesn't match `\r` either, and `(?m)$` matches before `\r\n`
+    crlf: bool,
+}
+
+/// Swaps in the forms of `.`, `^` and `$` that `flags` ask for throughout
+/// `tokens`.
+fn apply_flags(tokens: &mut [Token], flags: Flags) {
+    for token in tokens {
+        match token {
+            Token::WildCard if flags.dot_all => *token = Token::AnyChar,
+            Token::WildCard if flags.crlf => {
+                *token = Token::NegCharGroup(CharSet {
+                    chars: vec!['\n', '\r'],
+                    ..CharSet::default()
+                })
+            }
+            Token::StartAnchor if flags.multi_line => *token = Token::LineStart,
+            Token::EndAnchor if flags.multi_line && flags.crlf => *token = Token::CrlfLineEnd,
+            Token::EndAnchor if flags.multi_line => *token = Token::LineEnd,
+            Token::Alternation(branches) => {
+                for branch in branches {
+                    apply_flags(branch, flags);
+                }
+            }
+            Token::CaptureGroup(_, _, inner) | Token::Group(inner) => apply_flags(inner, flags),
+            Token::Quantifier(inner, _)
+            | Token::ExactRepetition(inner, _)
+            | Token::RangeRepetition(inner, _, _) => {
+                apply_flags(std::slice::from_mut(inner.as_mut()), flags)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Regex {
+    /// Compiles `pattern`; with `crlf`, for input whose lines end with `\r\n`.
+    pub fn new(pattern: &str, syntax: Syntax, crlf: bool) -> Regex {
+        let (dot_all, multi_line, pattern) = match syntax {
+            Syntax::Perl => inline_flags(pattern),
+            _ => (false, false, pattern),
+        };
+        let mut tokens = match syntax {
+            Syntax::Basic => tokenize(&basic::to_extended(pattern), Syntax::Extended),
+            _ => tokenize(pattern, syntax),
+        };
+        apply_flags(
+            &mut tokens,
+            Flags {
+                dot_all,
+                multi_line,
+                crlf,
+            },
+        );
+        let mut group_counter = 1;
+        assign_group_numbers(&mut tokens, &mut group_counter);
+        let mut names = group_names(&tokens);
+        names.resize(group_counter - 1, None);
+        let program = Program::compile(&tokens, syntax != Syntax::Perl);
+        let literal = literal::required(&tokens)
+            .map(|literal| memchr::memmem::Finder::new(literal.as_bytes()).into_owned());
+        Regex {
+            tokens,
+            names,
+            program,
+            literal,
+        }
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// The name of every capture group, in group number order (`None` for
+    /// unnamed groups).
+    pub fn group_names(&self) -> &[Option<String>] {
+        &self.names
+    }
+
+    /// The leftmost match that starts at or after char `start` of `input`.
+    /// `config` only turns on `--debug` logging.
+    pub fn find_at(
+        &self,
+        input: &[char],
+        start: usize,
+        config: Option<&Config>,
+    ) -> Option<Captures> {
+        if let Some(program) = &self.program {
+            return program.find_at(input, start, self.names.len());
+        }
+        (start..=input.len()).find_map(|pos| {
+            let mut groups = Vec::new();
+            let len = matches_from_range(input, &self.tokens, pos, config, &mut groups)?;
+            groups.resize(self.names.len(), None);
+            Some(((pos, pos + len), groups))
+        })
+    }
+
+    /// Whether a line with the raw bytes `line` could match at all: `false` only
+    /// if it lacks a literal every match contains, so it needn't be decoded.
+    pub fn may_match(&self, line: &[u8]) -> bool {
+        self.literal
+            .as_ref()
+            .is_none_or(|finder| finder.find(line).is_some())
+    }
+
+    pub fn is_match(&self, input: &[char], config: Option<&Config>) -> bool {
+        self.find_at(input, 0, config).is_some()
+    }
+
+    /// The successive non-overlapping matches in `input`, see `Matches`.
+    pub fn find_iter<'r, 'i>(&'r self, input: &'i [char]) -> Matches<'r, 'i> {
+        Matches {
+            regex: self,
+            input,
+            pos: 0,
+            last_end: None,
+        }
+    }
+}
+
+/// Iterator over the matches of a `Regex`, each searched for from where the
+/// previous one ended.
+///
+/// Empty matches are included, except one right where the previous match ended,
+/// and after one the search moves on by a char: `a*` finds 0..0, 1..4 and 5..5
+/// in `baaac`, as in sed. Output that shows the matches themselves (`-o`,
+/// highlighting, `--format`, match counts) leaves empty ones out, while
+/// `--replace` replaces them too.
+pub struct Matches<'r, 'i> {
+    regex: &'r Regex,
+    input: &'i [char],
+    pos: usize,
+    last_end: Option<usize>,
+}
+
+impl Iterator for Matches<'_, '_> {
+    type Item = Captures;
+
+    fn next(&mut self) -> Option<Captures> {
+        loop {
+            let found = self.regex.find_at(self.input, self.pos, None)?;
+            let (start, end) = found.0;
+            if start == end {
+                self.pos = end + 1;
+                if self.last_end == Some(end) {
+                    continue;
+                }
+            } else {
+                self.pos = end;
+            }
+            self.last_end = Some(end);
+            return Some(found);
+        }
+    }
+}
@@ -1,6 +1,17 @@
+use crate::parser::bracket;
+use crate::parser::input::raw_byte_char;
+use crate::parser::regex::Syntax;
 use crate::parser::types::{Quantifiers, Token};
 
-pub fn tokenize(pattern: &str) -> Vec<Token> {
+pub fn tokenize(pattern: &str, syntax: Syntax) -> Vec<Token> {
+    // A top-level `|` splits the whole pattern into branches, so anchors in any
+    // of them (`^a|b$`) apply to that branch alone
+    let branches = split_top_level(pattern, '|', syntax);
+    if branches.len() > 1 {
+        let branches = branches.iter().map(|b| tokenize(b, syntax)).collect();
+        return vec![Token::Alternation(branches)];
+    }
+
     let mut tokens = Vec::new();
     let mut chars = pattern.chars().peekable();
 
@@ -9,6 +20,7 @@ pub fn tokenize(pattern: &str) -> Vec<Token> {
             '\\' => match chars.next() {
                 Some('d') => Token::Digit,
                 Some('w') => Token::Word,
+                Some('n') => Token::Literal('\n'),
                 Some(c) if c.is_ascii_digit() => {
                     // Handle backreferences \1, \2, etc.
                     let mut num_str = c.to_string();
@@ -25,7 +37,27 @@ pub fn tokenize(pattern: &str) -> Vec<Token> {
                         _ => Token::Literal(c), // fallback for invalid backref
                     }
                 }
-                Some(c) => Token::Literal(c),
+                Some('x') => {
+                    // \xHH: ASCII bytes are plain literals, bytes >= 0x80 only match
+                    // that raw byte in input that isn't valid UTF-8
+                    let hex: String = chars.clone().take(2).collect();
+                    match u8::from_str_radix(&hex, 16) {
+                        Ok(byte) if hex.len() == 2 => {
+                            chars.next();
+                            chars.next();
+                            if byte.is_ascii() {
+                                Token::Literal(byte as char)
+                            } else {
+                                Token::Literal(raw_byte_char(byte))
+                            }
+                        }
+                        _ => Token::Literal('x'),
+                    }
+                }
+                Some(c) => match perl_escape(c) {
+                    Some(token) if syntax == Syntax::Perl => token,
+                    _ => Token::Literal(c),
+                },
                 None => continue,
             },
             '.' => Token::WildCard,
@@ -33,54 +65,40 @@ pub fn tokenize(pattern: &str) -> Vec<Token> {
             '$' => Token::EndAnchor,
 
             '[' => {
-                let mut group = Vec::new();
-                let mut neg = false;
-
-                if chars.peek() == Some(&'^') {
-                    neg = true;
-                    chars.next();
-                }
-
-                while let Some(c) = chars.next() {
-                    if c == ']' {
-                        break;
+                let rest: Vec<char> = std::iter::once('[').chain(chars.clone()).collect();
+                match bracket::parse(&rest, 0, syntax) {
+                    Some((token, end)) => {
+                        chars.nth(end - 2);
+                        token
                     }
-                    group.push(c);
-                }
-
-                if neg {
-                    Token::NegCharGroup(group)
-                } else {
-                    Token::CharGroup(group)
+                    // An unclosed `[` is a literal
+                    None => Token::Literal('['),
                 }
             }
             '(' => {
-                let mut depth = 1;
-                let mut content = String::new();
-
-                while let Some(c) = chars.next() {
-                    match c {
-                        '(' => depth += 1,
-                        ')' => {
-                            depth -= 1;
-                            if depth == 0 {
-                                break;
-                            }
-                        }
-                        _ => {}
-                    }
-                    content.push(c);
+                let rest: String = chars.clone().collect();
+                let end = closing_paren(&rest, syntax).unwrap_or(rest.len());
+                let content = rest[..end].to_string();
+                for _ in rest[..(end + 1).min(rest.len())].chars() {
+                    chars.next();
                 }
 
-                // Check if this contains alternation
-                if content.contains('|') {
-                    let parts = split_top_level(&content, '|');
-                    let branches = parts.into_iter().map(|p| tokenize(&p)).collect();
-                    Token::CaptureGroup(0, vec![Token::Alternation(branches)])
+                // tokenize splits a top-level alternation into an Alternation token
+                if syntax == Syntax::Perl
+                    && let Some(content) = content.strip_prefix("?:")
+                {
+                    Token::Group(tokenize(content, syntax))
                 } else {
-                    // Simple capturing group
-                    let inner_tokens = tokenize(&content);
-                    Token::CaptureGroup(0, inner_tokens)
+                    // Named groups: (?P<name>...) or (?<name>...)
+                    let (name, content) = match content
+                        .strip_prefix("?P<")
+                        .or_else(|| content.strip_prefix("?<"))
+                        .and_then(|rest| rest.split_once('>'))
+                    {
+                        Some((name, rest)) => (Some(name.to_string()), rest.to_string()),
+                        None => (None, content),
+                    };
+                    Token::CaptureGroup(0, name, tokenize(&content, syntax))
                 }
             }
             other => Token::Literal(other),
@@ -89,18 +107,7 @@ pub fn tokenize(pattern: &str) -> Vec<Token> {
         // Check for quantifier after token creation
         if let Some(&next) = chars.peek() {
             match next {
-                '+' => {
-                    chars.next();
-                    token = Token::Quantifier(Box::new(token), Quantifiers::OneOrMore);
-                }
-                '?' => {
-                    chars.next();
-                    token = Token::Quantifier(Box::new(token), Quantifiers::ZeroOrOne);
-                }
-                '*' => {
-                    chars.next();
-                    token = Token::Quantifier(Box::new(token), Quantifiers::ZeroOrMore);
-                }
+                '+' | '?' | '*' => token = quantify(token, &mut chars, syntax),
                 '{' => {
                     chars.next(); // consume '{'
                     let mut num_str = String::new();
@@ -108,7 +115,7 @@ pub fn tokenize(pattern: &str) -> Vec<Token> {
                     let mut max: Option<usize> = None;
                     let mut parsing_max = false;
 
-                    while let Some(c) = chars.next() {
+                    for c in chars.by_ref() {
                         if c == '}' {
                             break;
                         } else if c == ',' {
@@ -164,26 +171,94 @@ pub fn tokenize(pattern: &str) -> Vec<Token> {
     tokens
 }
 
-fn split_top_level(s: &str, sep: char) -> Vec<String> {
-    let mut parts = Vec::new();
-    let mut depth = 0;
-    let mut current = String::new();
+/// The token for a `-P` escape such as `\s` or `\t`.
+fn perl_escape(c: char) -> Option<Token> {
+    Some(match c {
+        's' => Token::Space,
+        'S' => Token::Negated(Box::new(Token::Space)),
+        'D' => Token::Negated(Box::new(Token::Digit)),
+        'W' => Token::Negated(Box::new(Token::Word)),
+        't' => Token::Literal('\t'),
+        'r' => Token::Literal('\r'),
+        'f' => Token::Literal('\x0c'),
+        'v' => Token::Literal('\x0b'),
+        'e' => Token::Literal('\x1b'),
+        _ => return None,
+    })
+}
 
-    for c in s.chars() {
+/// Wraps `token` in the `+`, `?` or `*` quantifier that follows it, if any; with
+/// `-P` a further `?` makes the quantifier lazy.
+fn quantify(
+    token: Token,
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    syntax: Syntax,
+) -> Token {
+    let quantifier = match chars.peek() {
+        Some('+') => Quantifiers::OneOrMore,
+        Some('?') => Quantifiers::ZeroOrOne,
+        Some('*') => Quantifiers::ZeroOrMore,
+        _ => return token,
+    };
+    chars.next();
+    if syntax == Syntax::Perl && chars.peek() == Some(&'?') {
+        chars.next();
+        return Token::Quantifier(Box::new(token), quantifier.lazy());
+    }
+    Token::Quantifier(Box::new(token), quantifier)
+}
+
+/// Calls `visit` with the byte index and char of every char of `s` that isn't
+/// escaped with a backslash or inside a bracket expression, along with the
+/// parenthesis depth at that point. Stops when `visit` returns false.
+fn scan_unescaped(s: &str, syntax: Syntax, mut visit: impl FnMut(usize, char, i32) -> bool) {
+    let (indices, chars): (Vec<usize>, Vec<char>) = s.char_indices().unzip();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
         match c {
-            '(' => depth += 1,
-            ')' => depth -= 1,
-            _ => {}
+            '\\' => i += 2,
+            '[' if let Some(end) = bracket::end(&chars, i, syntax) => i = end,
+            _ => {
+                if c == ')' {
+                    depth -= 1;
+                }
+                if !visit(indices[i], c, depth) {
+                    return;
+                }
+                if c == '(' {
+                    depth += 1;
+                }
+                i += 1;
+            }
         }
+    }
+}
 
-        if c == sep && depth == 0 {
-            parts.push(current);
-            current = String::new();
-        } else {
-            current.push(c);
+/// Byte index of the `)` closing a group whose contents start `s`.
+fn closing_paren(s: &str, syntax: Syntax) -> Option<usize> {
+    let mut end = None;
+    scan_unescaped(s, syntax, |i, c, depth| {
+        if c == ')' && depth < 0 {
+            end = Some(i);
+            return false;
         }
-    }
+        true
+    });
+    end
+}
 
-    parts.push(current);
+fn split_top_level(s: &str, sep: char, syntax: Syntax) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    scan_unescaped(s, syntax, |i, c, depth| {
+        if c == sep && depth == 0 {
+            parts.push(s[start..i].to_string());
+            start = i + c.len_utf8();
+        }
+        true
+    });
+    parts.push(s[start..].to_string());
     parts
 }
@@ -2,15 +2,22 @@
 pub enum Token {
     Digit,                                             // \d
     Word,                                              // \w
+    Space,                                             // \s with -P
+    Negated(Box<Token>),                               // \D, \W, \S with -P
     Literal(char),                                     // any literal character
-    CharGroup(Vec<char>),                              // [abc]
-    NegCharGroup(Vec<char>),                           // [^abc]
+    CharGroup(CharSet),                                // [abc]
+    NegCharGroup(CharSet),                             // [^abc]
     StartAnchor,                                       // ^log
     EndAnchor,                                         // log$
+    LineStart,                                         // ^ with (?m)
+    LineEnd,                                           // $ with (?m)
+    CrlfLineEnd,                                       // $ with (?m) and --crlf
     Quantifier(Box<Token>, Quantifiers),               // pig+ || pig? || pig*
     WildCard,                                          // p.g
+    AnyChar,                                           // . with (?s), newlines too
     Alternation(Vec<Vec<Token>>),                      // dog|pig
-    CaptureGroup(usize, Vec<Token>),                   // (group_number, captured content)
+    CaptureGroup(usize, Option<String>, Vec<Token>),   // (group_number, (?<name>...), content)
+    Group(Vec<Token>),                                 // (?:...) with -P
     BackReference(usize),                              // \1, \2, etc.
     ExactRepetition(Box<Token>, usize),                // a{3}
     RangeRepetition(Box<Token>, usize, Option<usize>), // a{2,} or a{2,4}
@@ -18,7 +25,122 @@ pub enum Token {
 
 #[derive(Debug, Clone)]
 pub enum Quantifiers {
-    OneOrMore,  // +
-    ZeroOrOne,  // ?
-    ZeroOrMore, // *
+    OneOrMore,      // +
+    ZeroOrOne,      // ?
+    ZeroOrMore,     // *
+    LazyOneOrMore,  // +? with -P
+    LazyZeroOrOne,  // ?? with -P
+    LazyZeroOrMore, // *? with -P
+}
+
+impl Quantifiers {
+    /// Whether fewer repetitions are tried first.
+    pub fn is_lazy(&self) -> bool {
+        matches!(
+            self,
+            Quantifiers::LazyOneOrMore | Quantifiers::LazyZeroOrOne | Quantifiers::LazyZeroOrMore
+        )
+    }
+
+    pub fn at_least_one(&self) -> bool {
+        matches!(self, Quantifiers::OneOrMore | Quantifiers::LazyOneOrMore)
+    }
+
+    pub fn at_most_one(&self) -> bool {
+        matches!(self, Quantifiers::ZeroOrOne | Quantifiers::LazyZeroOrOne)
+    }
+
+    /// The lazy form of a greedy quantifier.
+    pub fn lazy(self) -> Quantifiers {
+        match self {
+            Quantifiers::OneOrMore => Quantifiers::LazyOneOrMore,
+            Quantifiers::ZeroOrOne => Quantifiers::LazyZeroOrOne,
+            Quantifiers::ZeroOrMore => Quantifiers::LazyZeroOrMore,
+            lazy => lazy,
+        }
+    }
+}
+
+/// Start and end char index of what a capture group matched, end exclusive.
+pub type Span = (usize, usize);
+
+/// A named character class: `[:alpha:]` and the others POSIX defines inside
+/// bracket expressions, and `\d`, `\w` and `\s` in them with `-P`. Like the
+/// escapes outside brackets, they only cover ASCII.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    XDigit,
+    /// `\w`: alphanumerics and `_`
+    Word,
+}
+
+impl CharClass {
+    /// The class `[:name:]` stands for.
+    pub fn from_name(name: &str) -> Option<CharClass> {
+        Some(match name {
+            "alnum" => CharClass::Alnum,
+            "alpha" => CharClass::Alpha,
+            "blank" => CharClass::Blank,
+            "cntrl" => CharClass::Cntrl,
+            "digit" => CharClass::Digit,
+            "graph" => CharClass::Graph,
+            "lower" => CharClass::Lower,
+            "print" => CharClass::Print,
+            "punct" => CharClass::Punct,
+            "space" => CharClass::Space,
+            "upper" => CharClass::Upper,
+            "xdigit" => CharClass::XDigit,
+            _ => return None,
+        })
+    }
+
+    pub fn contains(self, c: char) -> bool {
+        match self {
+            CharClass::Alnum => c.is_ascii_alphanumeric(),
+            CharClass::Alpha => c.is_ascii_alphabetic(),
+            CharClass::Blank => c == ' ' || c == '\t',
+            CharClass::Cntrl => c.is_ascii_control(),
+            CharClass::Digit => c.is_ascii_digit(),
+            CharClass::Graph => c.is_ascii_graphic(),
+            CharClass::Lower => c.is_ascii_lowercase(),
+            CharClass::Print => c.is_ascii_graphic() || c == ' ',
+            CharClass::Punct => c.is_ascii_punctuation(),
+            CharClass::Space => c.is_ascii_whitespace() || c == '\x0b',
+            CharClass::Upper => c.is_ascii_uppercase(),
+            CharClass::XDigit => c.is_ascii_hexdigit(),
+            CharClass::Word => c.is_ascii_alphanumeric() || c == '_',
+        }
+    }
+}
+
+/// The members of a bracket expression, see `bracket::parse`.
+#[derive(Debug, Clone, Default)]
+pub struct CharSet {
+    pub chars: Vec<char>,
+    /// Inclusive `a-z` ranges
+    pub ranges: Vec<(char, char)>,
+    /// Classes, each with whether it's negated (`\D`, `\W` and `\S` with `-P`)
+    pub classes: Vec<(CharClass, bool)>,
+}
+
+impl CharSet {
+    pub fn contains(&self, c: char) -> bool {
+        self.chars.contains(&c)
+            || self.ranges.iter().any(|&(low, high)| low <= c && c <= high)
+            || self
+                .classes
+                .iter()
+                .any(|&(class, negated)| class.contains(c) != negated)
+    }
 }
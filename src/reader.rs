@@ -0,0 +1,179 @@
+use std::io::{self, Read};
+
+use crate::encoding::{Decoder, Decoding, Encoding};
+
+/// Anything `core::search_file` can pull lines from.
+pub trait LineSource {
+    /// Returns the next line without its terminator (`\n`, or NUL with `-z`), or
+    /// `None` once the input is exhausted. A final line without a terminator is
+    /// still returned.
+    fn next_line(&mut self) -> io::Result<Option<&[u8]>>;
+
+    /// The start of the input, for sniffing binary content, without consuming it.
+    /// Only valid before the first `next_line` call.
+    fn first_block(&mut self) -> io::Result<&[u8]>;
+
+    /// How the lines were decoded from the original input.
+    fn decoding(&self) -> Decoding;
+}
+
+/// Size of the chunks read from the underlying source.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Buffered line reader that pulls its input in fixed-size chunks.
+///
+/// Lines are handed out as slices into the internal buffer as soon as their
+/// terminator has been read, so matches can be printed while the source is still
+/// being written to (`tail -f app.log | rgrep ...`). A line that spans a chunk
+/// boundary is kept in the buffer, which grows when a single line is longer
+/// than the buffer, until the rest of it arrives.
+pub struct LineReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+    eof: bool,
+    terminator: u8,
+    decoding: Decoding,
+}
+
+impl<R: Read> LineReader<R> {
+    pub fn new(inner: R, terminator: u8) -> Self {
+        LineReader {
+            inner,
+            buf: vec![0; CHUNK_SIZE],
+            start: 0,
+            end: 0,
+            eof: false,
+            terminator,
+            decoding: Decoding::default(),
+        }
+    }
+
+    /// Lines of `inner` transcoded to UTF-8, as `--encoding` (`forced`) or a byte
+    /// order mark say.
+    pub fn decoded(
+        inner: R,
+        terminator: u8,
+        forced: Option<Encoding>,
+    ) -> io::Result<LineReader<Decoder<R>>> {
+        let decoder = Decoder::new(inner, forced)?;
+        let decoding = decoder.decoding();
+        Ok(LineReader {
+            decoding,
+            ..LineReader::new(decoder, terminator)
+        })
+    }
+
+    /// Moves the pending partial line to the front of the buffer, grows the buffer
+    /// if that line already fills it, and reads the next chunk behind it.
+    fn fill(&mut self) -> io::Result<()> {
+        if self.start > 0 {
+            self.buf.copy_within(self.start..self.end, 0);
+            self.end -= self.start;
+            self.start = 0;
+        }
+        if self.end == self.buf.len() {
+            self.buf.resize(self.buf.len() * 2, 0);
+        }
+
+        loop {
+            match self.inner.read(&mut self.buf[self.end..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(());
+                }
+                Ok(n) => {
+                    self.end += n;
+                    return Ok(());
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<R: Read> LineSource for LineReader<R> {
+    fn next_line(&mut self) -> io::Result<Option<&[u8]>> {
+        // Bytes after `start` already known not to contain a terminator
+        let mut scanned = 0;
+        loop {
+            let pending = &self.buf[self.start + scanned..self.end];
+            if let Some(offset) = pending.iter().position(|&b| b == self.terminator) {
+                let line_start = self.start;
+                let line_end = line_start + scanned + offset;
+                self.start = line_end + 1;
+                return Ok(Some(&self.buf[line_start..line_end]));
+            }
+
+            if self.eof {
+                if self.start == self.end {
+                    return Ok(None);
+                }
+                let line_start = self.start;
+                self.start = self.end;
+                return Ok(Some(&self.buf[line_start..self.end]));
+            }
+
+            scanned = self.end - self.start;
+            self.fill()?;
+        }
+    }
+
+    fn first_block(&mut self) -> io::Result<&[u8]> {
+        if self.end == 0 && !self.eof {
+            self.fill()?;
+        }
+        Ok(&self.buf[self.start..self.end])
+    }
+
+    fn decoding(&self) -> Decoding {
+        self.decoding
+    }
+}
+
+/// Lines of input that is already in memory, such as a memory-mapped file.
+/// Lines are slices of the input itself, nothing gets copied.
+pub struct SliceLines<'a> {
+    rest: &'a [u8],
+    terminator: u8,
+    decoding: Decoding,
+}
+
+impl<'a> SliceLines<'a> {
+    /// Lines of `input`, which is UTF-8 once its first `decoding.bom_len` bytes
+    /// are skipped.
+    pub fn new(input: &'a [u8], terminator: u8, decoding: Decoding) -> Self {
+        SliceLines {
+            rest: &input[decoding.bom_len..],
+            terminator,
+            decoding,
+        }
+    }
+}
+
+impl LineSource for SliceLines<'_> {
+    fn next_line(&mut self) -> io::Result<Option<&[u8]>> {
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+        let line = match self.rest.iter().position(|&b| b == self.terminator) {
+            Some(end) => {
+                let line = &self.rest[..end];
+                self.rest = &self.rest[end + 1..];
+                line
+            }
+            None => std::mem::take(&mut self.rest),
+        };
+        Ok(Some(line))
+    }
+
+    fn first_block(&mut self) -> io::Result<&[u8]> {
+        Ok(&self.rest[..self.rest.len().min(CHUNK_SIZE)])
+    }
+
+    fn decoding(&self) -> Decoding {
+        self.decoding
+    }
+}
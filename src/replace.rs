@@ -0,0 +1,103 @@
+use crate::parser::regex::Regex;
+
+/// One piece of a `--replace` template.
+#[derive(Debug, Clone)]
+enum Part {
+    Literal(String),
+    /// `$N` / `${N}` / `${name}`: group N, 0 being the whole match
+    Group(usize),
+}
+
+/// A parsed `--replace` template.
+///
+/// `$0` is the whole match, `$1`, `$2`, ... numbered groups and `$name` or
+/// `${name}` named ones (`(?<name>...)`); braces separate a reference from text
+/// that follows it (`${1}st`). `$$` is a literal `$`, as is a `$` not followed by
+/// a reference. Groups that didn't take part in a match expand to nothing;
+/// ones the pattern doesn't have are an error.
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parses `text`, checking its group references against `regex`.
+    pub fn new(text: &str, regex: &Regex) -> Result<Template, String> {
+        let names = regex.group_names();
+        let resolve = |reference: &str| match reference.parse::<usize>() {
+            Ok(number) if number <= names.len() => Ok(Part::Group(number)),
+            Ok(_) => Err(format!("no group ${} in the pattern", reference)),
+            Err(_) => names
+                .iter()
+                .position(|name| name.as_deref() == Some(reference))
+                .map(|index| Part::Group(index + 1))
+                .ok_or_else(|| format!("unknown group ${{{}}} in --replace", reference)),
+        };
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(dollar) = rest.find('$') {
+            literal.push_str(&rest[..dollar]);
+            let after = &rest[dollar + 1..];
+
+            let (reference, consumed) = if let Some(braced) = after.strip_prefix('{') {
+                match braced.find('}') {
+                    Some(end) => (Some(&braced[..end]), end + 2),
+                    None => (None, 0),
+                }
+            } else if let Some(after_dollar) = after.strip_prefix('$') {
+                literal.push('$');
+                rest = after_dollar;
+                continue;
+            } else {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                if end > 0 {
+                    (Some(&after[..end]), end)
+                } else {
+                    (None, 0)
+                }
+            };
+
+            match reference {
+                Some(reference) => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(resolve(reference)?);
+                    rest = &after[consumed..];
+                }
+                None => {
+                    literal.push('$');
+                    rest = after;
+                }
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Template { parts })
+    }
+
+    /// The replacement for one match: `whole` is the matched text and
+    /// `captures` the text of each group, as given by `core::group_texts`.
+    pub fn expand(&self, whole: &[u8], captures: &[Option<&[u8]>]) -> Vec<u8> {
+        let mut expanded = Vec::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => expanded.extend_from_slice(text.as_bytes()),
+                Part::Group(0) => expanded.extend_from_slice(whole),
+                Part::Group(number) => {
+                    if let Some(Some(text)) = captures.get(number - 1) {
+                        expanded.extend_from_slice(text);
+                    }
+                }
+            }
+        }
+        expanded
+    }
+}
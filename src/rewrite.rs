@@ -0,0 +1,240 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions, Permissions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::core::{self, Config, SearchError};
+use crate::parser::input::DecodedLine;
+use crate::reader::CHUNK_SIZE;
+use crate::replace::Template;
+
+/// Unchanged lines shown around each change in a `--dry-run` diff, as `diff -u`.
+const DIFF_CONTEXT: usize = 3;
+
+/// One line of a file being rewritten. The line ending (`\n`, `\r\n`, or nothing
+/// on an unterminated last line) is kept apart so it's written back unchanged.
+struct Line<'a> {
+    text: &'a [u8],
+    ending: &'a [u8],
+}
+
+fn split_lines(contents: &[u8]) -> Vec<Line<'_>> {
+    let mut lines = Vec::new();
+    let mut rest = contents;
+    while !rest.is_empty() {
+        let line_len = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(rest.len(), |i| i + 1);
+        let (line, after) = rest.split_at(line_len);
+        let text_len = if line.ends_with(b"\r\n") {
+            line.len() - 2
+        } else if line.ends_with(b"\n") {
+            line.len() - 1
+        } else {
+            line.len()
+        };
+        let (text, ending) = line.split_at(text_len);
+        lines.push(Line { text, ending });
+        rest = after;
+    }
+    lines
+}
+
+/// The lines that match, up to `-m` of them, as (line index, replaced text).
+fn replace_lines(lines: &[Line], config: &Config, template: &Template) -> Vec<(usize, Vec<u8>)> {
+    let mut replaced = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        if config.max_count.is_some_and(|max| replaced.len() >= max) {
+            break;
+        }
+        let line = DecodedLine::new(line.text);
+        if core::match_pattern(&line, config) {
+            let text = core::replace_matches(&line, &config.regex, template, None);
+            replaced.push((index, text));
+        }
+    }
+    replaced
+}
+
+/// `path` with `suffix` appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn write_temp(temp: &Path, contents: &[u8], permissions: Permissions) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+    file.write_all(contents)?;
+    file.set_permissions(permissions)?;
+    file.sync_all()
+}
+
+/// Replaces the contents of `path` without a moment where it's missing or half
+/// written: the new contents go to a temporary file next to it, which is then
+/// renamed over it. The file keeps its permissions, and a symlink keeps pointing
+/// at the rewritten file.
+fn write_atomically(path: &Path, contents: &[u8], backup: Option<&str>) -> io::Result<()> {
+    let path = fs::canonicalize(path)?;
+    let permissions = fs::metadata(&path)?.permissions();
+    let mut temp_name = OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(format!(".rgrep-{}.tmp", process::id()));
+    let temp = path.with_file_name(temp_name);
+
+    let result = write_temp(&temp, contents, permissions)
+        .and_then(|()| match backup {
+            Some(suffix) => fs::copy(&path, with_suffix(&path, suffix)).map(|_| ()),
+            None => Ok(()),
+        })
+        .and_then(|()| fs::rename(&temp, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+fn diff_line(out: &mut dyn Write, marker: u8, text: &[u8], ending: &[u8]) -> io::Result<()> {
+    out.write_all(&[marker])?;
+    out.write_all(text)?;
+    if ending.is_empty() {
+        out.write_all(b"\n\\ No newline at end of file\n")
+    } else {
+        out.write_all(ending)
+    }
+}
+
+/// A hunk header range: `start,count`, or just `start` for a single line.
+fn hunk_range(start: usize, count: usize) -> String {
+    if count == 1 {
+        format!("{}", start + 1)
+    } else {
+        format!("{},{}", start + 1, count)
+    }
+}
+
+/// Prints the changes to one file as a unified diff. Lines are only ever
+/// replaced one for one, so both sides of a hunk cover the same lines.
+fn print_diff(
+    out: &mut dyn Write,
+    path: &Path,
+    lines: &[Line],
+    changes: &[(usize, Vec<u8>)],
+) -> io::Result<()> {
+    out.write_all(b"--- ")?;
+    out.write_all(core::path_bytes(path))?;
+    out.write_all(b"\n+++ ")?;
+    out.write_all(core::path_bytes(path))?;
+    writeln!(out)?;
+
+    let mut first = 0;
+    while first < changes.len() {
+        // Changes whose context would touch or overlap share a hunk
+        let mut last = first;
+        while last + 1 < changes.len()
+            && changes[last + 1].0 - changes[last].0 <= 2 * DIFF_CONTEXT + 1
+        {
+            last += 1;
+        }
+        let start = changes[first].0.saturating_sub(DIFF_CONTEXT);
+        let end = (changes[last].0 + DIFF_CONTEXT + 1).min(lines.len());
+        let range = hunk_range(start, end - start);
+        writeln!(out, "@@ -{} +{} @@", range, range)?;
+
+        let mut index = start;
+        let mut next = first;
+        while index < end {
+            if next > last || changes[next].0 != index {
+                diff_line(out, b' ', lines[index].text, lines[index].ending)?;
+                index += 1;
+                continue;
+            }
+            // A run of adjacent changed lines: all removals, then all additions
+            let run_start = next;
+            while next <= last && changes[next].0 == index {
+                next += 1;
+                index += 1;
+            }
+            for (i, _) in &changes[run_start..next] {
+                diff_line(out, b'-', lines[*i].text, lines[*i].ending)?;
+            }
+            for (i, text) in &changes[run_start..next] {
+                diff_line(out, b'+', text, lines[*i].ending)?;
+            }
+        }
+        first = last + 1;
+    }
+    Ok(())
+}
+
+/// Rewrites one file, or prints its diff with `--dry-run`, and returns whether
+/// the pattern matched in it. Files where nothing actually changes are left
+/// untouched, and binary files are skipped unless `-a` is given.
+fn rewrite_file(
+    path: &Path,
+    config: &Config,
+    template: &Template,
+    out: &mut dyn Write,
+) -> Result<bool, SearchError> {
+    let contents = fs::read(path).map_err(SearchError::Input)?;
+    if core::looks_binary(&contents[..contents.len().min(CHUNK_SIZE)], config) {
+        return Ok(false);
+    }
+
+    let lines = split_lines(&contents);
+    let replaced = replace_lines(&lines, config, template);
+    let changes: Vec<_> = replaced
+        .iter()
+        .filter(|(index, text)| lines[*index].text != text.as_slice())
+        .cloned()
+        .collect();
+    if changes.is_empty() {
+        return Ok(!replaced.is_empty());
+    }
+
+    if config.dry_run {
+        print_diff(out, path, &lines, &changes)?;
+    } else {
+        let mut rewritten = Vec::with_capacity(contents.len());
+        let mut changes = changes.iter().peekable();
+        for (index, line) in lines.iter().enumerate() {
+            match changes.next_if(|(i, _)| *i == index) {
+                Some((_, text)) => rewritten.extend_from_slice(text),
+                None => rewritten.extend_from_slice(line.text),
+            }
+            rewritten.extend_from_slice(line.ending);
+        }
+        write_atomically(path, &rewritten, config.backup.as_deref()).map_err(SearchError::Input)?;
+    }
+    Ok(true)
+}
+
+/// `--in-place`: applies the `--replace` template to every file found by
+/// `core::collect_files_recursive`, or with `--dry-run` prints the changes as a
+/// unified diff instead. Returns whether the pattern matched in any file.
+pub fn process_files(config: &Config, template: &Template) -> Result<bool, Box<dyn Error>> {
+    // The whole tree is walked before anything is written, so the walk never
+    // comes across the temporary files
+    let mut paths = Vec::new();
+    core::collect_files_recursive(config, &mut |path| {
+        paths.push(path.to_path_buf());
+        true
+    });
+    if config.sort_by_path {
+        paths.sort();
+    }
+
+    let mut matched_any = false;
+    let mut out = io::stdout().lock();
+    for path in &paths {
+        match rewrite_file(path, config, template, &mut out) {
+            Ok(matched) => matched_any |= matched,
+            Err(SearchError::Input(e)) => core::report_error(config, &path.to_string_lossy(), &e),
+            Err(SearchError::Output(e)) => return Err(e.into()),
+        }
+    }
+    Ok(matched_any)
+}
@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    const FILES: &[(&str, &str)] = &[("b.bin", "foo\0bar\nfoo 2\n"), ("t.txt", "foo\n")];
+
+    #[test]
+    fn binary_match_is_reported_not_printed() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep -E foo {dir}/b.bin {dir}/t.txt"),
+        );
+        assert_eq!(
+            output,
+            format!("Binary file {dir}/b.bin matches\n{dir}/t.txt:foo\n")
+        );
+    }
+
+    #[test]
+    fn text_mode_prints_lines() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES);
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep -a -E 'foo 2' {}/b.bin",
+                fixture.path()
+            ),
+        );
+        assert_eq!(output, "foo 2\n");
+    }
+
+    #[test]
+    fn without_match_skips_binary_files() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep -I -E foo {dir}/b.bin {dir}/t.txt \
+                 && ./target/release/rgrep --binary-files=without-match -l -E foo {dir}/b.bin; echo $?"
+            ),
+        );
+        assert_eq!(output, format!("{dir}/t.txt:foo\n1\n"));
+    }
+}
@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn nested_groups_get_their_own_colors() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "echo 'say key=val ok'",
+                "./target/release/rgrep --color -E '((\\w+)=(\\w+))'",
+            ),
+            "say \x1b[01;33mkey\x1b[0m\x1b[01;32m=\x1b[0m\x1b[01;34mval\x1b[0m ok\n"
+        );
+    }
+
+    #[test]
+    fn colors_option_overrides_grep_colors() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "echo 'a=b'",
+                "GREP_COLORS='ms=04:g1=07:g2=07' ./target/release/rgrep --color --colors 'g2=' -E '(\\w)=(\\w)'",
+            ),
+            "\x1b[07ma\x1b[0m\x1b[04m=\x1b[0mb\n"
+        );
+    }
+
+    #[test]
+    fn file_names_separators_and_context() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture.file("f", "a\nb\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --color --colors 'cx=2:fn=34' -B1 -r -E b {dir}"),
+        );
+        assert_eq!(
+            output,
+            format!(
+                "\x1b[34m{dir}/f\x1b[0m\x1b[36m-\x1b[0m\x1b[2ma\x1b[0m\n\
+                 \x1b[34m{dir}/f\x1b[0m\x1b[36m:\x1b[0m\x1b[01;31mb\x1b[0m\n"
+            )
+        );
+    }
+
+    #[test]
+    fn explicit_color_wins_over_no_color() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "echo x",
+                "NO_COLOR=1 ./target/release/rgrep --color=always -E x",
+            ),
+            "\x1b[01;31mx\x1b[0m\n"
+        );
+    }
+}
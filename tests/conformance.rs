@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    /// Runs `rgrep <args>` over each case's input and checks its output.
+    fn check(args: &str, cases: &[(&str, &str, &str)]) {
+        is_rgrep_built();
+
+        for (pattern, input, expected) in cases {
+            let output = run_rgrep_output_from_root(
+                &format!("printf '{}'", input),
+                &format!("./target/release/rgrep {} '{}'", args, pattern),
+            );
+            assert_eq!(output, *expected, "pattern {:?} on {:?}", pattern, input);
+        }
+    }
+
+    #[test]
+    fn only_matching() {
+        check(
+            "-o -E",
+            &[
+                ("a*", "baaac\\n", "aaa\n"),
+                ("a+", "aa b aaa\\n", "aa\naaa\n"),
+                ("x*", "abc\\n", ""),
+                ("\\d", "a1b22\\n", "1\n2\n2\n"),
+                ("ab|a", "abab a\\n", "ab\nab\na\n"),
+                ("(a)|(b)", "ab\\n", "a\nb\n"),
+                ("a$|b", "abc\\nca\\n", "b\na\n"),
+                ("x|^a", "aba\\n", "a\n"),
+                ("(^a|c)", "abca\\n", "a\nc\n"),
+                ("b*$", "abb\\n", "bb\n"),
+            ],
+        );
+    }
+
+    #[test]
+    fn replace_empty_matches() {
+        check(
+            "-E --replace '<$0>'",
+            &[
+                ("a*", "baaac\\n", "<>b<aaa>c<>\n"),
+                ("X*", "aXbXc\\n", "<>a<X>b<X>c<>\n"),
+                ("^", "ab\\n", "<>ab\n"),
+                ("$", "ab\\n", "ab<>\n"),
+                ("(a)|(b)", "ab\\n", "<a><b>\n"),
+            ],
+        );
+    }
+
+    #[test]
+    fn perl_syntax() {
+        check(
+            "-o -P",
+            &[
+                ("a+?", "aaa\\n", "a\na\na\n"),
+                ("<.+?>", "<a><b>\\n", "<a>\n<b>\n"),
+                ("a.??b", "ab a.b\\n", "ab\na.b\n"),
+                ("(?:ab)+", "ababx\\n", "abab\n"),
+                ("(?:ab){2}", "ababab\\n", "abab\n"),
+                ("(?:a|bc){2,}x", "abcax bx\\n", "abcax\n"),
+            ],
+        );
+        // Without -P the same patterns are matched greedily
+        check("-o -E", &[("<.+>", "<a><b>\\n", "<a><b>\n")]);
+        check("-P --replace '$1'", &[("(?:a)(b)", "ab\\n", "b\n")]);
+    }
+
+    #[test]
+    fn perl_escapes() {
+        check(
+            "-o -P",
+            &[
+                ("\\S+\\s", "ab cd\\tx\\n", "ab \ncd\t\n"),
+                ("\\D\\W", "1a-b\\n", "a-\n"),
+                ("a\\tb", "a\\tb\\n", "a\tb\n"),
+            ],
+        );
+        // Only -P knows them
+        check("-o -E", &[("\\s", "s \\n", "s\n")]);
+    }
+
+    #[test]
+    fn basic_syntax() {
+        check(
+            "-o",
+            &[
+                ("a+b", "a+b aab\\n", "a+b\n"),
+                ("a\\+b", "a+b aab\\n", "aab\n"),
+                ("\\(ab\\)\\{2\\}", "ababx\\n", "abab\n"),
+                ("(a|b)", "(a|b) ab\\n", "(a|b)\n"),
+                ("cat\\|dog", "cat dog\\n", "cat\ndog\n"),
+                ("*y", "x*y\\n", "*y\n"),
+                ("a^b", "a^b\\n", "a^b\n"),
+                ("\\(a\\)x\\1", "axa\\n", "axa\n"),
+            ],
+        );
+        check("-G -o", &[("b?", "ab?\\n", "b?\n")]);
+    }
+
+    #[test]
+    fn leftmost_longest() {
+        let cases = [
+            ("a|ab", "abc\\n", "ab\n"),
+            ("(a|ab)(c|bcd)", "abcd\\n", "abcd\n"),
+            ("x*|foo", "foo\\n", "foo\n"),
+            ("(ab|a)(bc)?", "abc\\n", "abc\n"),
+        ];
+        check("-o -E", &cases);
+        check("-o", &[("a\\|ab", "abc\\n", "ab\n")]);
+        // -P takes the first alternative that leads to a match
+        check(
+            "-o -P",
+            &[
+                ("a|ab", "abc\\n", "a\n"),
+                ("(a|ab)(c|bcd)", "abcd\\n", "abcd\n"),
+                ("x*|foo", "foo\\n", ""),
+            ],
+        );
+        check(
+            "-E --replace '[$1][$2]'",
+            &[("(a|ab)(c|bcd)", "abcd\\n", "[a][bcd]\n")],
+        );
+    }
+
+    #[test]
+    fn bracket_expressions() {
+        let cases = [
+            ("[]a]", "x]y\\n", "]\n"),
+            ("[^]a]+", "a]bc\\n", "bc\n"),
+            ("[a-c]+", "abc-\\n", "abc\n"),
+            ("[a-]+", "a-b\\n", "a-\n"),
+            ("[^a-c]+", "abxyc\\n", "xy\n"),
+            ("[[:digit:]]+", "ab12c\\n", "12\n"),
+            ("[[:alpha:]_]+", "1ab_c2\\n", "ab_c\n"),
+            ("[^[:space:][:punct:]]+", "a, bc!\\n", "a\nbc\n"),
+            ("[]|]+", "a]|b\\n", "]|\n"),
+        ];
+        check("-o -E", &cases);
+        check("-o -P", &cases);
+        let basic: Vec<(String, &str, &str)> = cases
+            .iter()
+            .map(|(pattern, input, expected)| (pattern.replace('+', "\\+"), *input, *expected))
+            .collect();
+        let basic: Vec<(&str, &str, &str)> =
+            basic.iter().map(|(p, i, e)| (p.as_str(), *i, *e)).collect();
+        check("-o", &basic);
+        // Escapes only work in brackets with -P
+        check("-o -P", &[("[\\d\\]]+", "a1]b\\n", "1]\n")]);
+        check("-o -E", &[("[\\d]+", "d\\\\x\\n", "d\\\n")]);
+    }
+}
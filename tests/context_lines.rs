@@ -0,0 +1,64 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn print_context_around_matches() {
+        is_rgrep_built();
+
+        let input = "printf '1\\n2\\nfoo\\n4\\n5\\n6\\n7\\nfoo\\n9\\nfoo\\n11\\n'";
+
+        let result1 = run_rgrep_output_from_root(input, "./target/release/rgrep -C 1 -E foo");
+        assert_eq!(result1, "2\nfoo\n4\n--\n7\nfoo\n9\nfoo\n11\n");
+
+        let result2 = run_rgrep_output_from_root(input, "./target/release/rgrep -A1 -E foo");
+        assert_eq!(result2, "foo\n4\n--\nfoo\n9\nfoo\n11\n");
+
+        let result3 = run_rgrep_output_from_root(
+            input,
+            "./target/release/rgrep -B 1 --no-group-separator -E foo",
+        );
+        assert_eq!(result3, "2\nfoo\n7\nfoo\n9\nfoo\n");
+
+        let result4 = run_rgrep_output_from_root(
+            input,
+            "./target/release/rgrep -A 1 --group-separator=XX -E foo",
+        );
+        assert_eq!(result4, "foo\n4\nXX\nfoo\n9\nfoo\n11\n");
+
+        let result5 = run_rgrep_output_from_root(
+            input,
+            "./target/release/rgrep -A 1 --group-separator '== next ==' -E foo",
+        );
+        assert_eq!(result5, "foo\n4\n== next ==\nfoo\n9\nfoo\n11\n");
+
+        // An empty separator still prints its (empty) line
+        let result6 = run_rgrep_output_from_root(
+            input,
+            "./target/release/rgrep -A 1 --group-separator '' -E foo",
+        );
+        assert_eq!(result6, "foo\n4\n\nfoo\n9\nfoo\n11\n");
+    }
+
+    #[test]
+    fn separate_groups_from_different_files() {
+        is_rgrep_built();
+        let fixture = TempDir::with_files(&[("a", "x\nfoo\ny\n"), ("c", "foo\nz\n")]);
+        let dir = fixture.path();
+        let expected = format!("{dir}/a-x\n{dir}/a:foo\n{dir}/a-y\n--\n{dir}/c:foo\n{dir}/c-z\n");
+        for threads in [1, 4] {
+            let result = run_rgrep_output_from_root(
+                "true",
+                &format!("./target/release/rgrep -j {threads} --sort path -C1 foo {dir}/a {dir}/c"),
+            );
+            assert_eq!(result, expected);
+        }
+
+        // Without context, matches from different files aren't separated
+        let result = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep -j 1 foo {dir}/a {dir}/c"),
+        );
+        assert_eq!(result, format!("{dir}/a:foo\n{dir}/c:foo\n"));
+    }
+}
@@ -0,0 +1,36 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn count_list_and_quiet_modes() {
+        is_rgrep_built();
+
+        let input = "printf 'foo foo\\nbar\\nfoo\\n'";
+
+        let result1 = run_rgrep_output_from_root(input, "./target/release/rgrep -c -E foo");
+        assert_eq!(result1, "2\n");
+
+        let result2 =
+            run_rgrep_output_from_root(input, "./target/release/rgrep --count-matches -E foo");
+        assert_eq!(result2, "3\n");
+
+        let result3 = run_rgrep_output_from_root(input, "./target/release/rgrep -l -E bar");
+        assert_eq!(result3, "(standard input)\n");
+
+        let result4 = run_rgrep_output_from_root(input, "./target/release/rgrep -L -E baz");
+        assert_eq!(result4, "(standard input)\n");
+
+        let result5 = run_rgrep_output_from_root(input, "./target/release/rgrep -q -E foo");
+        assert_eq!(result5, "");
+
+        assert!(run_rgrep_from_root(
+            input,
+            "./target/release/rgrep -q -E foo"
+        ));
+        assert!(!run_rgrep_from_root(
+            input,
+            "./target/release/rgrep -q -E baz"
+        ));
+    }
+}
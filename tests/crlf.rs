@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    const INPUT: &str = "printf 'foo\\r\\nbar foo\\r\\nbaz\\n'";
+
+    #[test]
+    fn crlf_line_endings() {
+        is_rgrep_built();
+
+        // Without --crlf the \r is part of the line, so foo$ never matches
+        assert!(!run_rgrep_from_root(
+            INPUT,
+            "./target/release/rgrep -E 'foo$'"
+        ));
+        // Matching lines keep their \r\n
+        assert_eq!(
+            run_rgrep_output_from_root(INPUT, "./target/release/rgrep --crlf -E 'foo$'"),
+            "foo\r\nbar foo\r\n"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(INPUT, "./target/release/rgrep --crlf -o -E 'o.$'"),
+            "oo\noo\n"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(
+                INPUT,
+                "./target/release/rgrep --crlf --replace '<$0>' -E 'o$'"
+            ),
+            "fo<o>\r\nbar fo<o>\r\n"
+        );
+        // . doesn't match the \r either, even with -U
+        assert_eq!(
+            run_rgrep_output_from_root(INPUT, "./target/release/rgrep --crlf -U -c -E 'foo.'"),
+            "0\n"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(
+                INPUT,
+                "./target/release/rgrep --crlf -U -c -P '(?m)foo$\\r\\nbar'"
+            ),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn crlf_auto_detection() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture.file("dos", "foo\r\n").file("unix", "foo\nfoo\r\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --crlf=auto -c -E 'foo$' {dir}/dos {dir}/unix"),
+        );
+        // Only files whose first line ends with \r\n get --crlf
+        assert_eq!(output, format!("{dir}/dos:1\n{dir}/unix:1\n"));
+    }
+}
@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    // "hi\nfoo bar\n" in UTF-16LE, with its byte order mark
+    const UTF16: &str = "printf '\\377\\376h\\0i\\0\\n\\0f\\0o\\0o\\0 \\0b\\0a\\0r\\0\\n\\0'";
+
+    #[test]
+    fn utf16_from_byte_order_mark() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(UTF16, "./target/release/rgrep -E 'foo'"),
+            "foo bar\n"
+        );
+        // Offsets are those of the original UTF-16 bytes
+        assert_eq!(
+            run_rgrep_output_from_root(UTF16, "./target/release/rgrep -b -E 'foo'"),
+            "8:foo bar\n"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(UTF16, "./target/release/rgrep -b -o -E 'bar'"),
+            "16:bar\n"
+        );
+        // Taken as UTF-8, the NUL bytes get in the way
+        assert!(!run_rgrep_from_root(
+            UTF16,
+            "./target/release/rgrep --encoding utf-8 -E 'foo'"
+        ));
+    }
+
+    #[test]
+    fn single_byte_encodings() {
+        is_rgrep_built();
+
+        let input = "printf 'caf\\351\\n\\200 price\\n'";
+        assert_eq!(
+            run_rgrep_output_from_root(input, "./target/release/rgrep --encoding latin1 -b 'é'"),
+            "0:café\n"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(
+                input,
+                "./target/release/rgrep --encoding=windows-1252 -b -o '€ price'"
+            ),
+            "5:€ price\n"
+        );
+        // A UTF-8 byte order mark is skipped, but still counts for offsets
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "printf '\\357\\273\\277abc\\nabd\\n'",
+                "./target/release/rgrep -b '^ab'"
+            ),
+            "3:abc\n7:abd\n"
+        );
+    }
+
+    #[test]
+    fn byte_offsets_of_context_lines() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "printf 'one\\ntwo\\nthree\\n'",
+                "./target/release/rgrep -b -B1 two"
+            ),
+            "0-one\n4:two\n"
+        );
+    }
+}
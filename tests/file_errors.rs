@@ -0,0 +1,94 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    const FILES: &[(&str, &str)] = &[("a", "foo\n"), ("d/b", "foo\n")];
+    const LINKS: &[(&str, &str)] = &[("d/broken", "nowhere")];
+
+    #[test]
+    fn errors_are_reported_and_search_continues() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES).with_symlinks(LINKS);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep -E foo {dir}/missing {dir}/d {dir}/a 2>&1; echo $?"),
+        );
+        assert_eq!(
+            output,
+            format!(
+                "rgrep: {dir}/missing: No such file or directory\n\
+                 rgrep: {dir}/d: Is a directory\n\
+                 {dir}/a:foo\n2\n"
+            )
+        );
+    }
+
+    #[test]
+    fn broken_symlinks_are_reported_when_following() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES).with_symlinks(LINKS);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --sort path -R -E foo {dir}/d 2>&1; echo $?"),
+        );
+        assert_eq!(
+            output,
+            format!("rgrep: {dir}/d/broken: No such file or directory\n{dir}/d/b:foo\n2\n")
+        );
+    }
+
+    #[test]
+    fn no_messages_keeps_exit_status() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES).with_symlinks(LINKS);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep -s -E foo {dir}/missing {dir}/a 2>&1; echo $? \
+                 && ./target/release/rgrep -q -E foo {dir}/missing {dir}/a 2>/dev/null; echo $?"
+            ),
+        );
+        assert_eq!(output, format!("{dir}/a:foo\n2\n0\n"));
+    }
+
+    #[test]
+    fn permission_errors_are_reported_and_search_continues() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES).with_symlinks(LINKS);
+        fixture.file("d/locked", "foo\n").dir("d/closed");
+        let dir = fixture.path();
+        let lock = |mode| {
+            for name in ["d/locked", "d/closed"] {
+                fs::set_permissions(format!("{dir}/{name}"), fs::Permissions::from_mode(mode))
+                    .unwrap();
+            }
+        };
+        lock(0o000);
+        // Permissions don't stop root, who reads both as if nothing was locked
+        let privileged = fs::read(format!("{dir}/d/locked")).is_ok();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --sort path -r -E foo {dir}/d 2>&1; echo $?"),
+        );
+        lock(0o755);
+        let expected = if privileged {
+            format!("{dir}/d/b:foo\n{dir}/d/locked:foo\n0\n")
+        } else {
+            format!(
+                "rgrep: {dir}/d/closed: Permission denied\n\
+                 {dir}/d/b:foo\n\
+                 rgrep: {dir}/d/locked: Permission denied\n2\n"
+            )
+        };
+        assert_eq!(output, expected);
+    }
+}
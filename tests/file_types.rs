@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    const FILES: &[(&str, &str)] = &[
+        ("src/main.rs", "foo\n"),
+        ("src/app.py", "foo\n"),
+        ("docs/README.md", "foo\n"),
+        ("Cargo.toml", "foo\n"),
+    ];
+
+    #[test]
+    fn type_and_type_not() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep --sort path -r -t rust -t py -E foo {dir} \
+                 && ./target/release/rgrep --sort path -r -T rust -Tpy -Tmd -E foo {dir}"
+            ),
+        );
+        assert_eq!(
+            output,
+            format!("{dir}/src/app.py:foo\n{dir}/src/main.rs:foo\n{dir}/Cargo.toml:foo\n")
+        );
+    }
+
+    #[test]
+    fn type_not_narrows_type() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep --sort path -r --type-add 'code:*.{{rs,py}}' -t code -T py -E foo {dir} \
+                 && ./target/release/rgrep -r -t rust -T rust -E foo {dir}; echo $?"
+            ),
+        );
+        // A file of both a selected and a rejected type is left out
+        assert_eq!(output, format!("{dir}/src/main.rs:foo\n1\n"));
+    }
+
+    #[test]
+    fn type_add_and_list() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep -r --type-add 'cargo:Cargo.{{toml,lock}}' --type cargo -E foo {dir} \
+                 && ./target/release/rgrep --type-add cargo:Cargo.toml --type-list | grep -E '^(cargo|rust):'"
+            ),
+        );
+        assert_eq!(
+            output,
+            format!("{dir}/Cargo.toml:foo\ncargo: Cargo.toml\nrust: *.rs\n")
+        );
+    }
+}
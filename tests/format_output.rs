@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn placeholders_for_position_and_groups() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture.file("a", "x\nkey=val other=2\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep --format '{{path}}:{{line}}:{{column}}:{{offset}}: {{1}} -> {{v}}' \
+                    -E '(\\w+)=(?<v>\\w+)' {dir}/a"
+            ),
+        );
+        assert_eq!(
+            output,
+            format!("{dir}/a:2:1:2: key -> val\n{dir}/a:2:9:10: other -> 2\n")
+        );
+    }
+
+    #[test]
+    fn escapes_and_standard_input() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "echo 'a=1'",
+                "./target/release/rgrep --format='\\{{path}\\}\\t{0}\\n--' -E 'a=\\d'",
+            ),
+            "{(standard input)}\ta=1\n--\n"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholder_is_an_error() {
+        is_rgrep_built();
+
+        assert!(!run_rgrep_from_root(
+            "echo a",
+            "./target/release/rgrep --format '{name}' -E '(a)'"
+        ));
+    }
+}
@@ -0,0 +1,69 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    fn search(options: &str) -> String {
+        let dir = TempDir::with_files(&[
+            ("src/main.rs", "foo\n"),
+            ("src/a/lib.rs", "foo\n"),
+            ("src/a/t.toml", "foo\n"),
+            ("vendor/x/v.rs", "foo\n"),
+            ("docs/r.md", "foo\n"),
+            ("Cargo.toml", "foo\n"),
+        ]);
+        run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep --sort path -r {} -E foo {} | sed 's|^{}/||'",
+                options,
+                dir.path(),
+                dir.path()
+            ),
+        )
+    }
+
+    #[test]
+    fn include_and_exclude_dir() {
+        is_rgrep_built();
+
+        let output = search("--include='*.{rs,toml}' --exclude-dir=vendor");
+        assert_eq!(
+            output,
+            "Cargo.toml:foo\nsrc/a/lib.rs:foo\nsrc/a/t.toml:foo\nsrc/main.rs:foo\n"
+        );
+    }
+
+    #[test]
+    fn last_matching_filter_wins() {
+        is_rgrep_built();
+
+        let output = search("--exclude='*.rs' --include='**/a/*.rs' --exclude-dir='vend?r'");
+        assert_eq!(
+            output,
+            "Cargo.toml:foo\ndocs/r.md:foo\nsrc/a/lib.rs:foo\nsrc/a/t.toml:foo\n"
+        );
+    }
+
+    #[test]
+    fn overlapping_globs_follow_their_order() {
+        is_rgrep_built();
+
+        // Both globs match src/a/lib.rs, and whichever comes last decides. Files
+        // neither matches are searched unless the first filter is an include.
+        let output = search("--include='src/**' --exclude='*.rs'");
+        assert_eq!(output, "src/a/t.toml:foo\n");
+        let output = search("--exclude='*.rs' --include='src/**'");
+        assert_eq!(
+            output,
+            "Cargo.toml:foo\ndocs/r.md:foo\nsrc/a/lib.rs:foo\nsrc/a/t.toml:foo\nsrc/main.rs:foo\n"
+        );
+    }
+
+    #[test]
+    fn exclude_dir_overrides_a_matching_include() {
+        is_rgrep_built();
+
+        let output = search("--include='*.rs' --exclude-dir=a --exclude-dir=vendor");
+        assert_eq!(output, "src/main.rs:foo\n");
+    }
+}
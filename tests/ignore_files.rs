@@ -0,0 +1,143 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    const FILES: &[(&str, &str)] = &[
+        ("target/a", "foo\n"),
+        ("src/main.rs", "foo\n"),
+        ("src/gen/out.rs", "foo\n"),
+        ("src/keep.log", "foo\n"),
+        ("x.log", "foo\n"),
+        ("docs/n.txt", "foo\n"),
+        (".hidden/h", "foo\n"),
+        (".dot", "foo\n"),
+        (".gitignore", "target/\n*.log\n!src/keep.log\n"),
+        ("src/.ignore", "gen/\n"),
+        (".rgrepignore", "/docs\n"),
+    ];
+
+    #[test]
+    fn ignore_files_are_respected() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --sort path -r -E foo {dir}"),
+        );
+        assert_eq!(
+            output,
+            format!("{dir}/src/keep.log:foo\n{dir}/src/main.rs:foo\n")
+        );
+    }
+
+    #[test]
+    fn no_ignore_and_hidden_search_everything() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES);
+        fixture.file(".git/HEAD", "foo\n");
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep --no-ignore --hidden --sort path -r -E foo {} | wc -l",
+                fixture.path()
+            ),
+        );
+        // Everything but the repository's own .git directory
+        assert_eq!(output.trim(), "8");
+    }
+
+    #[test]
+    fn global_excludes_file_is_read() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES);
+        fixture
+            .file("home/.gitconfig", "[core]\n\texcludesFile = ~/ignore\n")
+            .file("home/ignore", "main.rs\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("HOME={dir}/home ./target/release/rgrep -r -E foo {dir}/src"),
+        );
+        assert_eq!(output, format!("{dir}/src/keep.log:foo\n"));
+    }
+
+    #[test]
+    fn negation_only_reincludes_what_it_names() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture
+            .file(
+                ".gitignore",
+                "*.log\n!keep*.log\n!/sub/*.log\nkeep-not.log\n",
+            )
+            .file("a.log", "foo\n")
+            .file("keep.log", "foo\n")
+            .file("keep-not.log", "foo\n")
+            .file("sub/b.log", "foo\n")
+            .file("sub/deeper/c.log", "foo\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --sort path -r -E foo {dir}"),
+        );
+        // The last matching rule wins, and `/sub/*.log` is anchored to the top
+        assert_eq!(output, format!("{dir}/keep.log:foo\n{dir}/sub/b.log:foo\n"));
+    }
+
+    #[test]
+    fn negation_cannot_reinclude_from_an_ignored_directory() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture
+            .file(".gitignore", "build/\n!build/keep.rs\n")
+            .file("build/keep.rs", "foo\n")
+            .file("main.rs", "foo\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --sort path -r -E foo {dir}"),
+        );
+        assert_eq!(output, format!("{dir}/main.rs:foo\n"));
+    }
+
+    #[test]
+    fn ignore_files_above_the_search_root_apply_up_to_the_repository_top() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture
+            .file(".gitignore", "*.txt\n")
+            .dir("repo/.git")
+            .file("repo/.gitignore", "*.log\n/sub/skip\n/skip2\n")
+            .file("repo/sub/.ignore", "!b.log\n")
+            .file("repo/sub/a.log", "foo\n")
+            .file("repo/sub/b.log", "foo\n")
+            .file("repo/sub/skip", "foo\n")
+            .file("repo/sub/skip2", "foo\n")
+            .file("repo/sub/keep.txt", "foo\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep --sort path -r -E foo {dir}/repo/sub \
+                 && (cd {dir}/repo/sub && $OLDPWD/target/release/rgrep --sort path -r -E foo) \
+                 && ./target/release/rgrep --no-ignore -r -E foo {dir}/repo/sub | wc -l"
+            ),
+        );
+        // `/sub/skip` is anchored to the repository top, and the .gitignore
+        // above the repository doesn't count
+        assert_eq!(
+            output,
+            format!(
+                "{dir}/repo/sub/b.log:foo\n{dir}/repo/sub/keep.txt:foo\n{dir}/repo/sub/skip2:foo\n\
+                 b.log:foo\nkeep.txt:foo\nskip2:foo\n5\n"
+            )
+        );
+    }
+}
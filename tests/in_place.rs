@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn rewrites_files_keeping_line_endings_and_permissions() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture
+            .file("x.txt", "a foo\r\nb\r\nfoo foo")
+            .file("y.txt", "none\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "chmod 750 {dir}/x.txt \
+                 && ./target/release/rgrep -r --in-place --replace bar -E foo {dir} \
+                 && sed -n l {dir}/x.txt && wc -c < {dir}/x.txt && stat -c %a {dir}/x.txt && ls {dir}"
+            ),
+        );
+        assert_eq!(
+            output,
+            "a bar\\r$\nb\\r$\nbar bar$\n17\n750\nx.txt\ny.txt\n"
+        );
+    }
+
+    #[test]
+    fn dry_run_prints_a_diff_and_changes_nothing() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture.file("f", "1\n2\n3\n4 x\n5\n6\n7\n8\n9\n10\n11\n12 x\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep --dry-run --replace '[$0]' -E x {dir}/f && md5sum < {dir}/f"
+            ),
+        );
+        assert_eq!(
+            output,
+            format!(
+                "--- {dir}/f\n+++ {dir}/f\n@@ -1,7 +1,7 @@\n 1\n 2\n 3\n-4 x\n+4 [x]\n 5\n 6\n 7\n\
+                 @@ -9,4 +9,4 @@\n 9\n 10\n 11\n-12 x\n+12 [x]\n\
+                 6f398f9e4790c5757d99ba27f0e1093a  -\n"
+            )
+        );
+    }
+
+    #[test]
+    fn backup_keeps_the_original() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture.file("conf", "key=old\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep --in-place --backup=.bak --replace '$1=new' -E '(\\w+)=old' {dir}/conf \
+                 && cat {dir}/conf {dir}/conf.bak"
+            ),
+        );
+        assert_eq!(output, "key=new\nkey=old\n");
+    }
+}
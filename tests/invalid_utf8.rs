@@ -0,0 +1,31 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn search_input_that_is_not_utf8() {
+        is_rgrep_built();
+
+        // Latin-1 "café" followed by a line of binary garbage
+        let input = "printf 'caf\\351 ok\\nplain\\n\\377\\376 foo\\n'";
+
+        assert!(run_rgrep_from_root(input, "./target/release/rgrep -E foo"));
+
+        let result1 =
+            run_rgrep_output_from_root(input, "./target/release/rgrep -E 'caf. ok' | od -An -c");
+        assert_eq!(
+            result1.split_whitespace().collect::<Vec<_>>(),
+            ["c", "a", "f", "351", "o", "k", "\\n"]
+        );
+
+        let result2 =
+            run_rgrep_output_from_root(input, "./target/release/rgrep -o -E '\\xff.' | od -An -c");
+        assert_eq!(
+            result2.split_whitespace().collect::<Vec<_>>(),
+            ["377", "376", "\\n"]
+        );
+
+        let result3 = run_rgrep_output_from_root(input, "./target/release/rgrep -c -E '^pl'");
+        assert_eq!(result3, "1\n");
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn match_and_context_events() {
+        is_rgrep_built();
+
+        let output = run_rgrep_output_from_root(
+            "printf 'hello world\\nsay \"hi\"\\tthere\\n'",
+            "./target/release/rgrep --json -A1 -E '(wor)(ld)' | grep -v summary",
+        );
+        assert_eq!(
+            output,
+            "{\"type\":\"begin\",\"data\":{}}\n\
+             {\"type\":\"match\",\"data\":{\"lines\":{\"text\":\"hello world\"},\"line_number\":1,\"absolute_offset\":0,\
+             \"submatches\":[{\"match\":{\"text\":\"world\"},\"start\":6,\"end\":11,\"captures\":[{\"text\":\"wor\"},{\"text\":\"ld\"}]}]}}\n\
+             {\"type\":\"context\",\"data\":{\"lines\":{\"text\":\"say \\\"hi\\\"\\tthere\"},\"line_number\":2,\"absolute_offset\":12,\"submatches\":[]}}\n\
+             {\"type\":\"end\",\"data\":{\"binary\":false,\"stats\":{\"matched_lines\":1,\"matches\":1,\"bytes_searched\":27}}}\n"
+        );
+    }
+
+    #[test]
+    fn non_utf8_paths_and_lines_are_base64() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "(cd {} && printf 'caf\\351 foo\\n' > l$(printf '\\377')t \
+                 && $OLDPWD/target/release/rgrep --json -r -E foo .) | grep '\"match\"'",
+                fixture.path()
+            ),
+        );
+        assert_eq!(
+            output,
+            "{\"type\":\"match\",\"data\":{\"path\":{\"bytes\":\"Li9s/3Q=\"},\"lines\":{\"bytes\":\"Y2Fm6SBmb28=\"},\
+             \"line_number\":1,\"absolute_offset\":0,\
+             \"submatches\":[{\"match\":{\"text\":\"foo\"},\"start\":5,\"end\":8,\"captures\":[]}]}}\n"
+        );
+    }
+
+    #[test]
+    fn summary_totals_all_files() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture.file("a", "foo foo\nbar\n").file("b", "bar\n");
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep --json -r -E foo {} | grep type.:.summary \
+                 | sed 's/\"elapsed_total\":{{[^}}]*}},//'",
+                fixture.path()
+            ),
+        );
+        assert_eq!(
+            output,
+            "{\"type\":\"summary\",\"data\":{\"stats\":{\"searches\":2,\"searches_with_match\":1,\
+             \"matched_lines\":1,\"matches\":2,\"bytes_searched\":16}}}\n"
+        );
+    }
+
+    #[test]
+    fn binary_files_follow_binary_files_option() {
+        is_rgrep_built();
+
+        let input = "printf 'foo\\0\\nfoo\\n'";
+        // No line events, and the search ends at the first match
+        assert_eq!(
+            run_rgrep_output_from_root(
+                input,
+                "./target/release/rgrep --json foo | grep -v summary"
+            ),
+            "{\"type\":\"begin\",\"data\":{}}\n\
+             {\"type\":\"end\",\"data\":{\"binary\":true,\"stats\":{\"matched_lines\":1,\"matches\":1,\"bytes_searched\":5}}}\n"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(
+                input,
+                "./target/release/rgrep --json -I foo | grep -v summary"
+            ),
+            ""
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(
+                input,
+                "./target/release/rgrep --json -a foo | grep -c type.:.match"
+            ),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn offsets_of_transcoded_input() {
+        is_rgrep_built();
+
+        // "hi\nfoo bar\n" in UTF-16LE: submatch offsets count UTF-16 bytes too
+        let output = run_rgrep_output_from_root(
+            "printf '\\377\\376h\\0i\\0\\n\\0f\\0o\\0o\\0 \\0b\\0a\\0r\\0\\n\\0'",
+            "./target/release/rgrep --json bar | grep '\"match\"'",
+        );
+        assert_eq!(
+            output,
+            "{\"type\":\"match\",\"data\":{\"lines\":{\"text\":\"foo bar\"},\"line_number\":2,\"absolute_offset\":8,\
+             \"submatches\":[{\"match\":{\"text\":\"bar\"},\"start\":8,\"end\":14,\"captures\":[]}]}}\n"
+        );
+    }
+
+    #[test]
+    fn rejects_replace_and_only_matching() {
+        is_rgrep_built();
+
+        assert!(!run_rgrep_from_root(
+            "echo foo",
+            "./target/release/rgrep --json -o foo"
+        ));
+        assert!(!run_rgrep_from_root(
+            "echo foo",
+            "./target/release/rgrep --json --replace bar foo"
+        ));
+    }
+}
@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn stop_after_max_count() {
+        is_rgrep_built();
+
+        let result1 = run_rgrep_output_from_root(
+            "printf 'foo1\\nbar\\nfoo2\\nfoo3\\n'",
+            "./target/release/rgrep -m 2 -E foo",
+        );
+        assert_eq!(result1, "foo1\nfoo2\n");
+
+        let result2 = run_rgrep_output_from_root(
+            "printf 'foo1\\nbar\\nbaz\\nfoo2\\n'",
+            "./target/release/rgrep -m1 -A 1 -E foo",
+        );
+        assert_eq!(result2, "foo1\nbar\n");
+
+        // An endless input only terminates if reading actually stops
+        let result3 = run_rgrep_output_from_root("yes foo", "./target/release/rgrep -m 3 -E foo");
+        assert_eq!(result3, "foo\nfoo\nfoo\n");
+
+        let result4 =
+            run_rgrep_output_from_root("yes foo", "./target/release/rgrep --max-total 2 -E foo");
+        assert_eq!(result4, "foo\nfoo\n");
+    }
+
+    #[test]
+    fn long_options_take_separate_values() {
+        is_rgrep_built();
+
+        let input = "printf 'foo1\\nbar\\nfoo2\\nbaz\\nfoo3\\n'";
+        for (options, expected) in [
+            ("--max-count 2", "foo1\nfoo2\n"),
+            ("--max-count=2", "foo1\nfoo2\n"),
+            ("--max-count 1 --after-context 1", "foo1\nbar\n"),
+            ("--before-context 1 --max-count 2", "foo1\nbar\nfoo2\n"),
+            ("--context 1 -m 1", "foo1\nbar\n"),
+        ] {
+            let output = run_rgrep_output_from_root(
+                input,
+                &format!("./target/release/rgrep {options} -E foo"),
+            );
+            assert_eq!(output, expected, "{options}");
+        }
+
+        // A missing value is an error rather than the pattern
+        assert!(!run_rgrep_from_root(
+            "echo foo",
+            "./target/release/rgrep -E foo --max-count"
+        ));
+    }
+}
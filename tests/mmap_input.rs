@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn mapped_and_buffered_reads_agree() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture
+            .file("input.txt", b"a foo\nb\n\xff foo\nlast foo")
+            .file("empty.txt", "");
+        let dir = fixture.path();
+
+        let result1 = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --mmap -E 'foo' {dir}/input.txt"),
+        );
+        let result2 = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --no-mmap -E 'foo' {dir}/input.txt"),
+        );
+        assert_eq!(result1, "a foo\n\u{fffd} foo\nlast foo\n");
+        assert_eq!(result1, result2);
+
+        let result3 = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --mmap -c -E foo {dir}/empty.txt"),
+        );
+        assert_eq!(result3, "0\n");
+    }
+
+    #[test]
+    fn literal_prefilter_keeps_every_match() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture.file("f", b"x\xffy foo\nfooobar\nzz\nab-ab\ncaf\xc3\xa9\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "for pattern in 'x\\xFFy' 'fo+bar' 'o{{3}}b' '(ab)-\\1' '^(c)af.$'; do \
+                   ./target/release/rgrep -o -E \"$pattern\" {dir}/f; done \
+                 && ./target/release/rgrep -B1 -A1 -E 'zz' {dir}/f"
+            ),
+        );
+        // Lines without the literal aren't decoded, but still print as context
+        assert_eq!(
+            output,
+            "x\u{fffd}y\nfooobar\nooob\nab-ab\ncaf\u{e9}\nfooobar\nzz\nab-ab\n"
+        );
+    }
+}
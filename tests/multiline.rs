@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    const INPUT: &str = "printf 'one\\nfoo\\nbar baz\\nqux\\nfoo\\nend\\n'";
+
+    #[test]
+    fn matches_span_lines() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(INPUT, "./target/release/rgrep -U -E 'foo\\nbar'"),
+            "foo\nbar baz\n"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(INPUT, "./target/release/rgrep -U -o -E 'o\\nb'"),
+            "o\nb\n"
+        );
+        // Without -U lines are searched one at a time
+        assert!(!run_rgrep_from_root(
+            INPUT,
+            "./target/release/rgrep -E 'foo\\nbar'"
+        ));
+        assert_eq!(
+            run_rgrep_output_from_root(INPUT, "./target/release/rgrep -U -c -E 'foo\\nbar'"),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn inline_flags() {
+        is_rgrep_built();
+
+        // . only crosses lines with (?s), and ^ and $ only match at them with (?m)
+        assert!(!run_rgrep_from_root(
+            INPUT,
+            "./target/release/rgrep -U -E 'foo.bar'"
+        ));
+        assert_eq!(
+            run_rgrep_output_from_root(INPUT, "./target/release/rgrep -U -P '(?s)foo.*qux'"),
+            "foo\nbar baz\nqux\n"
+        );
+        assert!(!run_rgrep_from_root(
+            INPUT,
+            "./target/release/rgrep -U -E 'foo$'"
+        ));
+        assert_eq!(
+            run_rgrep_output_from_root(INPUT, "./target/release/rgrep -U -o -P '(?m)^\\w+$'"),
+            "one\nfoo\nqux\nfoo\nend\n"
+        );
+    }
+
+    #[test]
+    fn inline_flags_are_literal_in_basic_regexes() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "printf '(?s)a\\nb\\na\\n'",
+                "./target/release/rgrep -U -G '(?s)a'"
+            ),
+            "(?s)a\n"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "printf '(?m)x\\nx\\n'",
+                "./target/release/rgrep -G '^(?m)x$'"
+            ),
+            "(?m)x\n"
+        );
+    }
+
+    #[test]
+    fn context_and_replace() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(INPUT, "./target/release/rgrep -U -A1 -E 'foo\\nbar'"),
+            "foo\nbar baz\nqux\n"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(
+                INPUT,
+                "./target/release/rgrep -U --replace '$1+$2' -E '(\\w+)\\n(\\w+)'"
+            ),
+            "one+foo\nbar baz+qux\nfoo+end\n"
+        );
+    }
+}
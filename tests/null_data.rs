@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn null_terminated_records() {
+        is_rgrep_built();
+
+        // Records can hold newlines, and output records end with a NUL too
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "printf 'one\\0two foo\\nmore\\0foo\\0'",
+                "./target/release/rgrep -z -E 'foo$'",
+            ),
+            "foo\0"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "printf 'one\\0two foo\\nmore\\0foo\\0'",
+                "./target/release/rgrep -z -c -E foo",
+            ),
+            "2\n"
+        );
+    }
+
+    #[test]
+    fn null_after_file_names() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture.file("a b", "foo\n").file("c", "bar\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep -Z -E foo '{dir}/a b' {dir}/c \
+                 && ./target/release/rgrep -l -Z -E 'foo|bar' '{dir}/a b' {dir}/c"
+            ),
+        );
+        assert_eq!(output, format!("{dir}/a b\0foo\n{dir}/a b\0{dir}/c\0"));
+    }
+
+    #[test]
+    fn files_from_list() {
+        is_rgrep_built();
+
+        let fixture = TempDir::new();
+        fixture
+            .file("a", "foo\n")
+            .file("b c", "foo\n")
+            .file("d", "bar\n");
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            &format!("find {dir} -type f -print0 | sort -z"),
+            "./target/release/rgrep --files-from - -l -E foo",
+        );
+        assert_eq!(output, format!("{dir}/a\n{dir}/b c\n"));
+
+        fixture.file("list", format!("{dir}/a\n{dir}/d\n"));
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --files-from={dir}/list -c -E foo"),
+        );
+        assert_eq!(output, format!("{dir}/a:1\n{dir}/d:0\n"));
+    }
+}
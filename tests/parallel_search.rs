@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    /// Nine files in `a` with a match on their first and last line, and two more
+    /// in directories of their own.
+    fn files() -> Vec<(String, String)> {
+        let mut files: Vec<(String, String)> = (1..=9)
+            .map(|i| (format!("a/f{i}.txt"), format!("foo {i}\nbar\nfoo again\n")))
+            .collect();
+        files.push(("a/b/y.txt".into(), "foo b\n".into()));
+        files.push(("c/x.txt".into(), "foo c\n".into()));
+        files
+    }
+
+    #[test]
+    fn sorted_output_is_deterministic() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(&files());
+        let dir = fixture.path();
+        let serial = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep -j 1 --sort path -r -E foo {dir}"),
+        );
+        let parallel = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep -j 8 --sort path -r -E foo {dir}"),
+        );
+        assert_eq!(serial, parallel);
+        assert!(serial.starts_with(&format!("{dir}/a/b/y.txt:foo b\n{dir}/a/f1.txt:foo 1\n")));
+        assert!(serial.ends_with(&format!("{dir}/c/x.txt:foo c\n")));
+    }
+
+    #[test]
+    fn lines_of_a_file_stay_together() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(&files());
+        let result = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep -j 4 -r -E foo {}", fixture.path()),
+        );
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines.len(), 20);
+        for (i, line) in lines.iter().enumerate() {
+            if line.contains("/a/f") && !line.ends_with("again") {
+                let file = line.split(':').next().unwrap();
+                assert_eq!(lines[i + 1], format!("{}:foo again", file));
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn numbered_groups() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "printf 'date 2024-01-15 and 2023-12-31\\nnothing\\n'",
+                "./target/release/rgrep --replace '$3/$2/$1' -E '(\\d+)-(\\d+)-(\\d+)'",
+            ),
+            "date 15/01/2024 and 31/12/2023\n"
+        );
+    }
+
+    #[test]
+    fn named_groups_whole_match_and_dollar() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "echo 'on 2024-01-15'",
+                "./target/release/rgrep --replace='${d}.${m} [$0] $$5|' -E '(?<y>\\d+)-(?P<m>\\d+)-(?<d>\\d+)'",
+            ),
+            "on 15.01 [2024-01-15] $5|\n"
+        );
+    }
+
+    #[test]
+    fn unknown_groups_are_an_error() {
+        is_rgrep_built();
+
+        assert!(!run_rgrep_from_root(
+            "echo a",
+            "./target/release/rgrep --replace '${nope}' -E '(?<y>a)'"
+        ));
+        assert!(!run_rgrep_from_root(
+            "echo a",
+            "./target/release/rgrep --replace '$2' -E '(a)'"
+        ));
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "echo a",
+                "./target/release/rgrep --replace '[$1]' -E '(a)' 2>&1",
+            ),
+            "[a]\n"
+        );
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "echo a",
+                "./target/release/rgrep --replace '$9' -E '(a)' 2>&1",
+            ),
+            "no group $9 in the pattern\n"
+        );
+    }
+
+    #[test]
+    fn only_matching_prints_each_replacement() {
+        is_rgrep_built();
+
+        assert_eq!(
+            run_rgrep_output_from_root(
+                "echo 'a=1, b=22'",
+                "./target/release/rgrep -o --replace '${1}:$2' -E '(\\w)=(\\d+)'",
+            ),
+            "a:1\nb:22\n"
+        );
+    }
+}
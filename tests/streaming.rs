@@ -0,0 +1,35 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    #[test]
+    fn lines_spanning_chunk_boundaries() {
+        is_rgrep_built();
+
+        // 200000 bytes is several read chunks, so the first line spans a few of them
+        let result = run_rgrep_output_from_root(
+            "(head -c 200000 /dev/zero | tr '\\0' a; printf 'foo\\nbar\\nfoo')",
+            "./target/release/rgrep -c -E 'a{3}foo$'",
+        );
+        assert_eq!(result, "1\n");
+
+        let result2 = run_rgrep_output_from_root(
+            "(head -c 200000 /dev/zero | tr '\\0' a; printf 'foo\\nbar\\nfoo')",
+            "./target/release/rgrep -E '^(foo|bar)$'",
+        );
+        assert_eq!(result2, "bar\nfoo\n");
+    }
+
+    #[test]
+    fn print_matches_before_input_ends() {
+        is_rgrep_built();
+
+        // rgrep gets killed while the writer is still sleeping, so the first match
+        // only shows up if it is printed as soon as its line has been read
+        let result = run_rgrep_output_from_root(
+            "(printf 'skip\\nfoo\\n'; sleep 2; printf 'foo again\\n')",
+            "timeout 1 ./target/release/rgrep -E foo",
+        );
+        assert_eq!(result, "foo\n");
+    }
+}
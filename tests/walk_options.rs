@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod tests {
+    use rsgrep::*;
+
+    const FILES: &[(&str, &str)] = &[
+        ("top", "foo\n"),
+        ("a/one", "foo\n"),
+        ("a/b/two", "foo\n"),
+        ("other/o", "foo\n"),
+    ];
+    const LINKS: &[(&str, &str)] = &[("a/b/loop", "../.."), ("a/link", "../other")];
+
+    #[test]
+    fn r_skips_symlinks() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES).with_symlinks(LINKS);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --sort path -r -E foo {dir}/a"),
+        );
+        assert_eq!(output, format!("{dir}/a/b/two:foo\n{dir}/a/one:foo\n"));
+    }
+
+    #[test]
+    fn follow_detects_loops() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES).with_symlinks(LINKS);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!("./target/release/rgrep --sort path -R -E foo {dir} 2>&1; echo $?"),
+        );
+        assert_eq!(
+            output,
+            format!(
+                "rgrep: warning: {dir}/a/b/loop: recursive directory loop\n\
+                 {dir}/a/b/two:foo\n{dir}/a/link/o:foo\n{dir}/a/one:foo\n\
+                 {dir}/other/o:foo\n{dir}/top:foo\n0\n"
+            )
+        );
+    }
+
+    #[test]
+    fn max_depth_limits_descent() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES).with_symlinks(LINKS);
+        let dir = fixture.path();
+        let output = run_rgrep_output_from_root(
+            "true",
+            &format!(
+                "./target/release/rgrep --sort path -r --max-depth 1 -E foo {dir} \
+                 && ./target/release/rgrep -r --max-depth=0 -E foo {dir}; echo $?"
+            ),
+        );
+        assert_eq!(output, format!("{dir}/top:foo\n1\n"));
+    }
+
+    #[test]
+    fn r_without_files_searches_working_directory() {
+        is_rgrep_built();
+
+        let fixture = TempDir::with_files(FILES).with_symlinks(LINKS);
+        let output = run_rgrep_output_from_root(
+            "echo foo",
+            &format!(
+                "(cd {}/a && $OLDPWD/target/release/rgrep --sort path -r -E foo \
+                 && $OLDPWD/target/release/rgrep --sort path -r -E foo .)",
+                fixture.path()
+            ),
+        );
+        // Standard input isn't read, and as in GNU grep only an explicit `.`
+        // shows up in the paths
+        assert_eq!(output, "b/two:foo\none:foo\n./b/two:foo\n./one:foo\n");
+    }
+}